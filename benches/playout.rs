@@ -0,0 +1,59 @@
+//! Random playouts from the empty board, comparing `game::Game` with `bitboard::BitBoard`,
+//! and from generated mid-game positions. Run with `cargo bench`.
+
+#![feature(test)]
+
+extern crate rand;
+extern crate test;
+extern crate tictactoe;
+
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use test::Bencher;
+use tictactoe::bitboard::BitBoard;
+use tictactoe::board::Ownership;
+use tictactoe::coord;
+use tictactoe::game::Game;
+use tictactoe::generate::{self, Bias};
+use tictactoe::rules::Rules;
+
+fn rng() -> XorShiftRng {
+    XorShiftRng::from_seed([7; 16])
+}
+
+#[bench]
+fn playout_game(b: &mut Bencher) {
+    let mut rng = rng();
+    let mut moves: Vec<coord::Global> = vec![];
+    b.iter(|| {
+        let mut game = Game::new();
+        while game.outcome() == Ownership::Undecided {
+            moves.clear();
+            moves.extend(game.legal_moves());
+            let position = moves[rng.gen_range(0, moves.len())];
+            game.play(position).unwrap();
+        }
+        game.outcome()
+    });
+}
+
+#[bench]
+fn playout_bitboard(b: &mut Bencher) {
+    let mut rng = rng();
+    b.iter(|| BitBoard::new().playout(&mut rng));
+}
+
+#[bench]
+fn playout_bitboard_midgame(b: &mut Bencher) {
+    let mut rng = rng();
+    let positions: Vec<BitBoard> = (0..16)
+        .map(|seed| generate::random_game(Rules::default(), 30, Bias::AvoidRegionLosses, seed))
+        .map(|game| BitBoard::from(&game))
+        .collect();
+    let mut i = 0;
+    b.iter(|| {
+        i = (i + 1) % positions.len();
+        let mut board = positions[i];
+        board.playout(&mut rng)
+    });
+}
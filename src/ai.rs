@@ -0,0 +1,442 @@
+//! Computer opponents.
+
+use board;
+use coord;
+use game::Game;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use transposition::TranspositionTable;
+
+/// Something which picks moves for the player to move.
+pub trait Bot {
+    /// Returns `None` if there is no legal move, i.e. the game is over.
+    fn choose_move(&mut self, game: &Game) -> Option<coord::Global>;
+}
+
+/// What the search thread of a `Worker` sends back: the bot, to search again later, and
+/// the move it chose.
+type Answer = (Box<dyn Bot + Send>, Option<coord::Global>);
+
+/// The search thread of a `Worker` panicked, and the bot is gone with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchPanicked;
+
+impl fmt::Display for SearchPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the computer opponent failed while searching for a move")
+    }
+}
+
+impl Error for SearchPanicked {}
+
+/// Runs a bot on its own thread, so a long search doesn't block the caller, e.g. the
+/// frames of the GUI.
+pub struct Worker {
+    /// `None` while the bot is searching.
+    bot: Option<Box<dyn Bot + Send>>,
+    /// The position being searched, in notation, and where the answer arrives.
+    search: Option<(String, Receiver<Answer>)>,
+}
+
+impl Worker {
+    pub fn new(bot: Box<dyn Bot + Send>) -> Self {
+        Worker {
+            bot: Some(bot),
+            search: None,
+        }
+    }
+
+    /// Asks for a move in the game, returning `None` until the bot has chosen one. Call it
+    /// again with the same game to get the answer. If the game changed in the meantime,
+    /// the answer is dropped and the new position searched.
+    ///
+    /// Once the search thread panicked, this and every later call return an error.
+    pub fn poll(&mut self, game: &Game) -> Result<Option<coord::Global>, SearchPanicked> {
+        let position = game.to_string();
+        let finished = match self.search {
+            Some((ref searched, ref receiver)) => match receiver.try_recv() {
+                Ok((bot, choice)) => Some((bot, choice, *searched == position)),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => None,
+            },
+            None => None,
+        };
+        self.search = None;
+        if let Some((bot, choice, current)) = finished {
+            self.bot = Some(bot);
+            if current {
+                return Ok(choice);
+            }
+        }
+
+        let mut bot = self.bot.take().ok_or(SearchPanicked)?;
+        let game = game.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let choice = bot.choose_move(&game);
+            // The worker may be gone, and the answer with it.
+            sender.send((bot, choice)).ok();
+        });
+        self.search = Some((position, receiver));
+        Ok(None)
+    }
+}
+
+/// The score of a won game. Heuristic scores stay far below this.
+const WIN: i32 = 1_000_000;
+
+/// Owning a region is worth more in the center and less on the edges,
+/// depending on how many lines run through it.
+const REGION_WEIGHTS: [i32; 9] = [100, 80, 100, 80, 120, 80, 100, 80, 100];
+/// Two regions in a line, the third one still undecided.
+const META_THREAT: i32 = 150;
+/// Two tokens in a line inside an undecided region, the third cell still clear.
+const LOCAL_THREAT: i32 = 10;
+/// Being allowed to play anywhere.
+const FREE_MOVE: i32 = 40;
+/// Being sent to a region which can be captured right away.
+const SENT_TO_THREAT: i32 = 30;
+
+/// Scores beyond this are won or lost games rather than heuristic scores.
+const DECIDED: i32 = WIN / 2;
+
+/// The number of positions `AlphaBeta` remembers between searches.
+const TABLE_SIZE: usize = 1 << 16;
+
+/// How a stored score relates to the true score of the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// The search was cut off, the true score may be higher.
+    Lower,
+    /// No move reached alpha, the true score may be lower.
+    Upper,
+}
+
+/// What `AlphaBeta` stores in its transposition table.
+#[derive(Clone, Copy, Debug)]
+struct Evaluation {
+    /// Scores of decided games count the moves from this position instead of the remaining
+    /// depth, so they stay right when the position comes up with another depth left.
+    score: i32,
+    bound: Bound,
+    /// Searched first when the position comes up again.
+    best: Option<coord::Global>,
+}
+
+/// A minimax search with alpha-beta pruning, looking a fixed number of moves ahead.
+#[derive(Clone, Debug)]
+pub struct AlphaBeta {
+    pub depth: u32,
+    table: TranspositionTable<Evaluation>,
+}
+
+impl AlphaBeta {
+    pub fn new(depth: u32) -> Self {
+        AlphaBeta {
+            depth,
+            table: TranspositionTable::new(TABLE_SIZE),
+        }
+    }
+
+    /// Scores the position from the point of view of the player to move.
+    fn negamax(&mut self, game: &mut Game, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+        if let Some(score) = decided_score(game, depth) {
+            return score;
+        }
+        if depth == 0 {
+            return evaluate(game);
+        }
+
+        let hash = game.hash();
+        let mut moves: Vec<coord::Global> = game.legal_moves().collect();
+        if let Some(entry) = self.table.get(hash) {
+            let stored = entry.value;
+            if entry.depth >= depth {
+                let score = from_table(stored.score, depth);
+                match stored.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+            if let Some(i) = moves.iter().position(|&p| Some(p) == stored.best) {
+                moves.swap(0, i);
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best = -WIN - self.depth as i32 - 1;
+        let mut best_move = None;
+        for position in moves {
+            game.play(position).expect("Generated moves are legal.");
+            let score = -self.negamax(game, depth - 1, -beta, -alpha);
+            game.undo();
+
+            if score > best {
+                best = score;
+                best_move = Some(position);
+            }
+            alpha = i32::max(alpha, score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            hash,
+            depth,
+            Evaluation {
+                score: to_table(best, depth),
+                bound,
+                best: best_move,
+            },
+        );
+        best
+    }
+}
+
+impl Bot for AlphaBeta {
+    fn choose_move(&mut self, game: &Game) -> Option<coord::Global> {
+        let mut game = game.clone();
+        let moves: Vec<coord::Global> = game.legal_moves().collect();
+        let depth = u32::max(self.depth, 1);
+
+        let mut best = None;
+        let mut alpha = -WIN - depth as i32 - 1;
+        for position in moves {
+            game.play(position).expect("Generated moves are legal.");
+            let score = -self.negamax(&mut game, depth - 1, -WIN - depth as i32 - 1, -alpha);
+            game.undo();
+
+            if best.is_none() || score > alpha {
+                best = Some(position);
+                alpha = score;
+            }
+        }
+        best
+    }
+}
+
+/// A heuristic score of an undecided game from the point of view of the player to move.
+pub fn evaluate(game: &Game) -> i32 {
+    let player = game.current_player();
+    let board = game.board();
+
+    let tempo = match game.active_region() {
+        None => FREE_MOVE,
+        Some(region) if board[region].count_threats(player.into()) > 0 => SENT_TO_THREAT,
+        Some(_) => 0,
+    };
+
+    tempo + score(board, player) - score(board, player.other())
+}
+
+/// Scores a decided game from the point of view of the player to move. Usually the other
+/// player just completed a line, but not under misère rules or when counting regions.
+/// Dead games are scored as the draw they end in, which saves searching them to the end.
+fn decided_score(game: &Game, depth: u32) -> Option<i32> {
+    let token: board::Token = game.current_player().into();
+    match game.outcome() {
+        board::Ownership::Undecided if game.board().is_dead() => Some(0),
+        board::Ownership::Undecided => None,
+        board::Ownership::Draw => Some(0),
+        // Winning now is better than winning later, and losing later better than now.
+        winner if winner == token.into() => Some(WIN + depth as i32),
+        _ => Some(-WIN - depth as i32),
+    }
+}
+
+/// Turns the score of a decided game, which depends on the depth left, into one which
+/// depends on how many moves the game lasts from this position.
+fn to_table(score: i32, depth: u32) -> i32 {
+    if score > DECIDED {
+        score - depth as i32
+    } else if score < -DECIDED {
+        score + depth as i32
+    } else {
+        score
+    }
+}
+
+/// Reverses `to_table` for the depth left when the position comes up again.
+fn from_table(score: i32, depth: u32) -> i32 {
+    if score > DECIDED {
+        score + depth as i32
+    } else if score < -DECIDED {
+        score - depth as i32
+    } else {
+        score
+    }
+}
+
+/// Everything the player has built up on the board, ignoring whose turn it is.
+fn score(board: &board::Global, player: board::Player) -> i32 {
+    let token: board::Token = player.into();
+    let owner: board::Ownership = token.into();
+
+    let mut score = META_THREAT * board.count_threats(owner) as i32;
+    for region in coord::Local::iter() {
+        let local = &board[region];
+        if local.total == owner {
+            score += REGION_WEIGHTS[region.index()];
+        } else if local.total == board::Ownership::Undecided {
+            score += LOCAL_THREAT * local.count_threats(token) as i32;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use ai::*;
+    use rules::{Misere, Rules};
+
+    #[quickcheck]
+    fn chooses_legal_moves(game: Game) -> bool {
+        match AlphaBeta::new(2).choose_move(&game) {
+            Some(position) => game.check(position).is_ok(),
+            None => game.outcome() != board::Ownership::Undecided,
+        }
+    }
+
+    #[test]
+    fn takes_winning_move() {
+        let game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                          O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            AlphaBeta::new(3).choose_move(&game),
+            Some("3/3".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn avoids_completing_a_line_under_misere() {
+        let game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                          O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        let mut board = game.board().clone();
+        board.set_rules(Rules {
+            misere: Misere::Game,
+            ..Rules::default()
+        });
+        let game = Game::from_position(board, game.current_player(), game.active_region());
+        let position = AlphaBeta::new(3).choose_move(&game).unwrap();
+        assert_ne!(position, "3/3".parse().unwrap());
+    }
+
+    /// Plain minimax without pruning or a table, scored like `negamax`.
+    fn minimax(game: &mut Game, depth: u32) -> i32 {
+        if let Some(score) = decided_score(game, depth) {
+            return score;
+        }
+        if depth == 0 {
+            return evaluate(game);
+        }
+        let moves: Vec<coord::Global> = game.legal_moves().collect();
+        moves
+            .into_iter()
+            .map(|position| {
+                game.play(position).unwrap();
+                let score = -minimax(game, depth - 1);
+                game.undo();
+                score
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[quickcheck]
+    fn table_does_not_change_scores(game: Game) -> bool {
+        let mut search = AlphaBeta::new(2);
+        let (alpha, beta) = (-WIN - 3, WIN + 3);
+        let expected = minimax(&mut game.clone(), 2);
+        // The second search finds the positions of the first one in the table.
+        search.negamax(&mut game.clone(), 2, alpha, beta) == expected
+            && search.negamax(&mut game.clone(), 2, alpha, beta) == expected
+    }
+
+    /// Polls the worker until it answers.
+    fn wait_for(worker: &mut Worker, game: &Game) -> Option<coord::Global> {
+        loop {
+            if let Some(choice) = worker.poll(game).unwrap() {
+                return Some(choice);
+            }
+            thread::sleep(::std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn worker_answers_like_the_bot() {
+        let game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                          O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        let mut worker = Worker::new(Box::new(AlphaBeta::new(3)));
+        assert_eq!(wait_for(&mut worker, &game), Some("3/3".parse().unwrap()));
+
+        // An answer for a position which has changed since is dropped.
+        let mut game = Game::new();
+        assert_eq!(worker.poll(&game), Ok(None));
+        game.play("5/5".parse().unwrap()).unwrap();
+        let choice = wait_for(&mut worker, &game).unwrap();
+        assert_eq!(
+            choice.get_region(),
+            "5/5".parse::<coord::Global>().unwrap().get_local()
+        );
+        assert_eq!(Some(choice), AlphaBeta::new(3).choose_move(&game));
+    }
+
+    /// A bot whose search always fails.
+    struct Panics;
+
+    impl Bot for Panics {
+        fn choose_move(&mut self, _game: &Game) -> Option<coord::Global> {
+            panic!("The search failed.");
+        }
+    }
+
+    #[test]
+    fn worker_reports_a_panicked_search() {
+        let game = Game::new();
+        let mut worker = Worker::new(Box::new(Panics));
+        let mut answer = worker.poll(&game);
+        while answer == Ok(None) {
+            thread::sleep(::std::time::Duration::from_millis(1));
+            answer = worker.poll(&game);
+        }
+        assert_eq!(answer, Err(SearchPanicked));
+        assert_eq!(worker.poll(&game), Err(SearchPanicked));
+    }
+
+    #[test]
+    fn table_keeps_win_distances() {
+        let game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                          O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        let (alpha, beta) = (-WIN - 5, WIN + 5);
+
+        // Cross wins with its next move, whatever the depth left.
+        let mut search = AlphaBeta::new(4);
+        assert_eq!(search.negamax(&mut game.clone(), 4, alpha, beta), WIN + 3);
+        // The second search finds the root in the table, stored with more depth left.
+        let shared = search.negamax(&mut game.clone(), 2, alpha, beta);
+        let fresh = AlphaBeta::new(2).negamax(&mut game.clone(), 2, alpha, beta);
+        assert_eq!(shared, WIN + 1);
+        assert_eq!(shared, fresh);
+    }
+}
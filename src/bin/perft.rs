@@ -0,0 +1,59 @@
+//! Counts the move sequences of a given length, split by the first move. This only needs
+//! the library, so it builds without the `gui` feature.
+
+#![warn(clippy)]
+
+extern crate tictactoe;
+
+use std::time::Instant;
+use std::{env, process};
+use tictactoe::game::Game;
+use tictactoe::perft;
+
+const USAGE: &str = "Usage: perft DEPTH [POSITION]
+
+Counts the move sequences of length DEPTH, split by the first move. POSITION uses the
+position notation and defaults to the empty board.";
+
+fn run(args: &mut dyn Iterator<Item = String>) -> Result<(), String> {
+    let depth = args.next().ok_or_else(|| "missing DEPTH".to_string())?;
+    let depth = depth
+        .parse()
+        .map_err(|_| format!("invalid depth '{}'", depth))?;
+    let mut game: Game = match args.next() {
+        Some(position) => position
+            .parse()
+            .map_err(|e| format!("invalid position '{}': {}", position, e))?,
+        None => Game::new(),
+    };
+    if let Some(arg) = args.next() {
+        return Err(format!("unknown argument '{}'", arg));
+    }
+
+    let start = Instant::now();
+    let total = if depth == 0 {
+        1
+    } else {
+        let mut total = 0;
+        for (position, count) in perft::divide(&mut game, depth) {
+            println!("{} {}", position, count);
+            total += count;
+        }
+        total
+    };
+    let elapsed = start.elapsed();
+    println!(
+        "\nDepth {}: {} ({} ms)",
+        depth,
+        total,
+        elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+    );
+    Ok(())
+}
+
+pub fn main() {
+    if let Err(e) = run(&mut env::args().skip(1)) {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    }
+}
@@ -0,0 +1,557 @@
+//! A compact board representation for fast search.
+//!
+//! Every region is stored as two 9 bit masks, one per player, with bit `i` standing
+//! for the cell with `coord::Local` index `i`. The meta-board uses the same layout.
+//! Unlike `game::Game`, this doesn't keep a history, so moves have to be taken back
+//! with the `Undo` returned by `make_move`.
+
+use board;
+use coord;
+use game::Game;
+use rand::Rng;
+use rules::{DeadGame, DrawnRegions, FreeMove, FullBoard, Misere, Rules};
+
+/// All nine cells of a region, or all nine regions of the meta-board.
+const FULL: u16 = 0x1FF;
+
+/// Marks the free move in `BitBoard::active`.
+const FREE: u8 = 9;
+
+/// The eight lines of a tic-tac-toe board as masks.
+const LINES: [u16; 8] = [0x7, 0x38, 0x1C0, 0x49, 0x92, 0x124, 0x111, 0x54];
+
+/// Bit `m` of this 512 bit table is set if the mask `m` contains a complete line.
+const WINS: [u64; 8] = [
+    0xff80808080808080,
+    0xfff0aa80faf0aa80,
+    0xffcc8080cccc8080,
+    0xfffcaa80fefcaa80,
+    0xfffaf0f0aaaa8080,
+    0xfffafaf0fafaaa80,
+    0xfffef0f0eeee8080,
+    0xffffffffffffffff,
+];
+
+/// The number of set bits in each 4 bit mask.
+const BIT_COUNTS: [u8; 16] = [0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4];
+
+/// The number of cells in a 9 bit mask. Unless the CPU has a popcount instruction, and
+/// the build is allowed to use it, this is faster than `count_ones`.
+fn count(mask: u16) -> u32 {
+    u32::from(BIT_COUNTS[(mask & 0xF) as usize])
+        + u32::from(BIT_COUNTS[(mask >> 4 & 0xF) as usize])
+        + u32::from(mask >> 8)
+}
+
+fn is_won(mask: u16) -> bool {
+    (WINS[(mask >> 6) as usize] >> (mask & 63)) & 1 == 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitBoard {
+    /// The cells taken by cross (index 0) and circle (index 1), one mask per region.
+    cells: [[u16; 9]; 2],
+    /// The regions won by cross and circle.
+    won: [u16; 2],
+    /// All decided regions, including draws.
+    decided: u16,
+    /// Index of the player to move into `cells` and `won`.
+    side: usize,
+    /// The region the player to move is sent to, or `FREE`.
+    active: u8,
+    /// Who owns the meta-board, kept up to date by `make_move` so playouts don't have to
+    /// evaluate it before every move.
+    total: board::Ownership,
+    rules: Rules,
+}
+
+/// Everything `unmake_move` needs to restore the position before a move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Undo {
+    won: [u16; 2],
+    decided: u16,
+    active: u8,
+    total: board::Ownership,
+}
+
+/// Maps a random number onto `0..n`. This is much cheaper than `gen_range`, which divides,
+/// and the bias is far too small to matter for playouts.
+fn scale(random: u32, n: u32) -> u32 {
+    ((u64::from(random) * u64::from(n)) >> 32) as u32
+}
+
+/// The index of the `n`th set bit, counting from the lowest. This counts the cells up to
+/// that bit without branching, which is faster than clearing bits in a loop whose length
+/// the CPU can't predict.
+fn nth_bit(mask: u16, n: u32) -> usize {
+    let mut seen = 0;
+    let mut index = 0;
+    for i in 0..9 {
+        seen += u32::from(mask >> i) & 1;
+        index += (seen <= n) as usize;
+    }
+    index
+}
+
+fn position(region: usize, local: usize) -> coord::Global {
+    coord::Global::new(
+        coord::Local::from_index(region),
+        coord::Local::from_index(local),
+    )
+}
+
+fn side_of(player: board::Player) -> usize {
+    match player {
+        board::Player::Cross => 0,
+        board::Player::Circle => 1,
+    }
+}
+
+fn player_owner(side: usize) -> board::Ownership {
+    if side == 0 {
+        board::Ownership::Cross
+    } else {
+        board::Ownership::Circle
+    }
+}
+
+fn player_of(side: usize) -> board::Player {
+    if side == 0 {
+        board::Player::Cross
+    } else {
+        board::Player::Circle
+    }
+}
+
+impl Default for BitBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BitBoard {
+    pub fn new() -> Self {
+        Self::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
+        BitBoard {
+            cells: [[0; 9]; 2],
+            won: [0; 2],
+            decided: 0,
+            side: 0,
+            active: FREE,
+            total: board::Ownership::Undecided,
+            rules,
+        }
+    }
+
+    /// Converts a position, where the active region has the same meaning as in `game::Game`.
+    pub fn from_board(
+        board: &board::Global,
+        current_player: board::Player,
+        active_region: Option<coord::Local>,
+    ) -> Self {
+        let mut result = Self::with_rules(board.rules());
+        for position in coord::Global::iter() {
+            let bit = 1 << position.get_local().index();
+            let region = position.get_region().index();
+            match board[position] {
+                board::Token::Cross => result.cells[0][region] |= bit,
+                board::Token::Circle => result.cells[1][region] |= bit,
+                board::Token::Clear => {}
+            }
+        }
+        for region in coord::Local::iter() {
+            let bit = 1 << region.index();
+            match board[region].total {
+                board::Ownership::Cross => result.won[0] |= bit,
+                board::Ownership::Circle => result.won[1] |= bit,
+                board::Ownership::Draw => {}
+                board::Ownership::Undecided => continue,
+            }
+            result.decided |= bit;
+        }
+        result.side = side_of(current_player);
+        result.active = active_region.map_or(FREE, |region| region.index() as u8);
+        result.total = result.evaluate_total();
+        result
+    }
+
+    /// Converts back to a board, which evaluates the regions from scratch.
+    /// See `board::Global::set_rules` for where this can differ from the bitboard.
+    pub fn to_board(&self) -> board::Global {
+        let mut tokens = [board::Token::Clear; 81];
+        for position in coord::Global::iter() {
+            let bit = 1 << position.get_local().index();
+            let region = position.get_region().index();
+            if self.cells[0][region] & bit != 0 {
+                tokens[position.index()] = board::Token::Cross;
+            } else if self.cells[1][region] & bit != 0 {
+                tokens[position.index()] = board::Token::Circle;
+            }
+        }
+        let mut board = board::Global::from_tokens(&tokens);
+        board.set_rules(self.rules);
+        board
+    }
+
+    pub fn to_game(&self) -> Game {
+        Game::from_position(self.to_board(), self.current_player(), self.active_region())
+    }
+
+    pub fn current_player(&self) -> board::Player {
+        player_of(self.side)
+    }
+
+    pub fn active_region(&self) -> Option<coord::Local> {
+        if self.active == FREE {
+            None
+        } else {
+            Some(coord::Local::from_index(self.active as usize))
+        }
+    }
+
+    /// The same as `game::Game::outcome`, including misère rules.
+    pub fn outcome(&self) -> board::Ownership {
+        match self.rules.misere {
+            Misere::Off => self.total,
+            Misere::Game | Misere::Regions => self.total.inverted(),
+        }
+    }
+
+    /// Who owns the meta-board, like `board::Global::total`, evaluated from scratch.
+    fn evaluate_total(&self) -> board::Ownership {
+        // Usually only the player who moved last can have completed a line. Drawn regions
+        // counting for both players or captures going to the opponent can complete a line
+        // for the player to move, too. If both have one, the player who moved last wins.
+        let last = 1 - self.side;
+        if self.has_line(last) {
+            player_owner(last)
+        } else if self.has_line(self.side) {
+            player_owner(self.side)
+        } else if self.decided == FULL {
+            let (cross, circle) = (self.won[0].count_ones(), self.won[1].count_ones());
+            if self.rules.full_board == FullBoard::Draw || cross == circle {
+                board::Ownership::Draw
+            } else if cross > circle {
+                board::Ownership::Cross
+            } else {
+                board::Ownership::Circle
+            }
+        } else if self.rules.dead_game == DeadGame::Draw && self.is_dead() {
+            board::Ownership::Draw
+        } else {
+            board::Ownership::Undecided
+        }
+    }
+
+    /// The same as `board::Global::is_dead`.
+    pub fn is_dead(&self) -> bool {
+        self.rules.full_board == FullBoard::Draw
+            && !self.can_complete_line(0)
+            && !self.can_complete_line(1)
+    }
+
+    /// Whether the side could still get a line of regions, like in `has_line`.
+    fn can_complete_line(&self, side: usize) -> bool {
+        let capturer = if self.rules.inverts_captures() {
+            1 - side
+        } else {
+            side
+        };
+        let mut can_win = self.won[side];
+        for region in 0..9 {
+            let blocked = self.cells[1 - capturer][region];
+            if self.decided & (1 << region) == 0 && LINES.iter().any(|&line| blocked & line == 0) {
+                can_win |= 1 << region;
+            }
+        }
+        let counts = match self.rules.drawn_regions {
+            DrawnRegions::Nobody => can_win,
+            // Undecided regions may still end in a draw.
+            DrawnRegions::Both => can_win | (FULL & !self.won[1 - side]),
+        };
+        LINES
+            .iter()
+            .any(|&line| counts & line == line && can_win & line != 0)
+    }
+
+    fn has_line(&self, side: usize) -> bool {
+        let won = self.won[side];
+        match self.rules.drawn_regions {
+            DrawnRegions::Nobody => is_won(won),
+            DrawnRegions::Both => {
+                let counts = won | (self.decided & !(self.won[0] | self.won[1]));
+                is_won(counts)
+                    && LINES
+                        .iter()
+                        .any(|&line| counts & line == line && won & line != 0)
+            }
+        }
+    }
+
+    /// The regions the player to move may play in, if they still have a clear cell.
+    fn playable_regions(&self) -> u16 {
+        if self.active != FREE {
+            1 << self.active
+        } else if self.rules.free_move == FreeMove::AnyRegion {
+            FULL
+        } else {
+            FULL & !self.decided
+        }
+    }
+
+    /// Appends all legal moves to the list, which is faster than allocating a new one.
+    pub fn legal_moves(&self, moves: &mut Vec<coord::Global>) {
+        if self.total != board::Ownership::Undecided {
+            return;
+        }
+        let regions = self.playable_regions();
+        for region in 0..9 {
+            if regions & (1 << region) == 0 {
+                continue;
+            }
+            let mut clear = self.clear(region);
+            while clear != 0 {
+                moves.push(position(region, clear.trailing_zeros() as usize));
+                clear &= clear - 1;
+            }
+        }
+    }
+
+    /// Picks one of the legal moves uniformly at random, without building a list.
+    pub fn random_move<R: Rng>(&self, rng: &mut R) -> Option<coord::Global> {
+        if self.total != board::Ownership::Undecided {
+            return None;
+        }
+        let (region, local) = self.random_cell(rng);
+        Some(position(region, local))
+    }
+
+    /// Plays random moves until the game is decided.
+    pub fn playout<R: Rng>(&mut self, rng: &mut R) -> board::Ownership {
+        while self.total == board::Ownership::Undecided {
+            let (region, local) = self.random_cell(rng);
+            self.place(region, local);
+        }
+        self.outcome()
+    }
+
+    /// The region and cell of a random legal move. The game must be undecided.
+    fn random_cell<R: Rng>(&self, rng: &mut R) -> (usize, usize) {
+        if self.active != FREE {
+            let region = self.active as usize;
+            let clear = self.clear(region);
+            let local = nth_bit(clear, scale(rng.next_u32(), count(clear)));
+            return (region, local);
+        }
+
+        let regions = self.playable_regions();
+        let mut clear = [0; 9];
+        let mut counts = [0; 9];
+        let mut total = 0;
+        for region in 0..9 {
+            if regions & (1 << region) != 0 {
+                clear[region] = self.clear(region);
+                counts[region] = count(clear[region]);
+                total += counts[region];
+            }
+        }
+
+        // Like in `nth_bit`, skip the regions before the move without branching.
+        let k = scale(rng.next_u32(), total);
+        let mut region = 0;
+        let mut before = 0;
+        let mut counted = 0;
+        for &count in &counts {
+            counted += count;
+            if counted <= k {
+                region += 1;
+                before = counted;
+            }
+        }
+        (region, nth_bit(clear[region], k - before))
+    }
+
+    /// The cells of the region which are still clear.
+    fn clear(&self, region: usize) -> u16 {
+        FULL & !(self.cells[0][region] | self.cells[1][region])
+    }
+
+    /// Places a token for the player to move. The move must be legal.
+    pub fn make_move(&mut self, position: coord::Global) -> Undo {
+        let undo = Undo {
+            won: self.won,
+            decided: self.decided,
+            active: self.active,
+            total: self.total,
+        };
+        self.place(position.get_region().index(), position.get_local().index());
+        undo
+    }
+
+    /// `make_move` without saving what it changes.
+    fn place(&mut self, region: usize, local: usize) {
+        let decided = self.decided;
+        let cells = self.cells[self.side][region] | (1 << local);
+        self.cells[self.side][region] = cells;
+        // Tokens in decided regions don't change the owner.
+        if self.decided & (1 << region) == 0 {
+            if is_won(cells) {
+                let owner = if self.rules.inverts_captures() {
+                    1 - self.side
+                } else {
+                    self.side
+                };
+                self.won[owner] |= 1 << region;
+                self.decided |= 1 << region;
+            } else if cells | self.cells[1 - self.side][region] == FULL {
+                self.decided |= 1 << region;
+            }
+        }
+
+        self.side = 1 - self.side;
+        self.active = if self.decided & (1 << local) == 0 {
+            local as u8
+        } else {
+            FREE
+        };
+        // Only a newly decided region can complete a line or fill the meta-board, but any
+        // token can block the last line left.
+        if self.decided != decided || self.rules.dead_game == DeadGame::Draw {
+            self.total = self.evaluate_total();
+        }
+    }
+
+    /// Takes back the last move, which has to be the given position.
+    pub fn unmake_move(&mut self, position: coord::Global, undo: Undo) {
+        let region = position.get_region().index();
+        let local = position.get_local().index();
+
+        self.side = 1 - self.side;
+        self.cells[self.side][region] &= !(1 << local);
+        self.won = undo.won;
+        self.decided = undo.decided;
+        self.active = undo.active;
+        self.total = undo.total;
+    }
+}
+
+impl<'a> From<&'a Game> for BitBoard {
+    fn from(game: &'a Game) -> Self {
+        BitBoard::from_board(game.board(), game.current_player(), game.active_region())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitboard::*;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    fn legal_moves(board: &BitBoard) -> Vec<coord::Global> {
+        let mut moves = vec![];
+        board.legal_moves(&mut moves);
+        moves
+    }
+
+    /// A game by the given rules, where each number picks one of the legal moves.
+    fn variant_game(rules: Rules, choices: Vec<u8>) -> Game {
+        let mut game = Game::with_rules(rules);
+        for choice in choices {
+            let moves: Vec<coord::Global> = game.legal_moves().collect();
+            if moves.is_empty() {
+                break;
+            }
+            game.play(moves[choice as usize % moves.len()]).unwrap();
+        }
+        game
+    }
+
+    #[quickcheck]
+    fn random_move_is_legal(game: Game, seed: u8) -> bool {
+        let board = BitBoard::from(&game);
+        let mut rng = XorShiftRng::from_seed([seed | 1; 16]);
+        match board.random_move(&mut rng) {
+            Some(position) => game.check(position).is_ok(),
+            None => game.outcome() != board::Ownership::Undecided,
+        }
+    }
+
+    #[test]
+    fn random_moves_cover_all_legal_moves() {
+        let mut board = BitBoard::new();
+        board.make_move("5/5".parse().unwrap());
+        board.make_move("5/1".parse().unwrap());
+
+        let mut rng = XorShiftRng::from_seed([1; 16]);
+        let mut seen = vec![];
+        for _ in 0..1000 {
+            let position = board.random_move(&mut rng).unwrap();
+            if !seen.contains(&position) {
+                seen.push(position);
+            }
+        }
+        seen.sort_by_key(|position| position.index());
+        assert_eq!(seen, legal_moves(&board));
+    }
+
+    #[test]
+    fn win_table_matches_lines() {
+        for mask in 0..512 {
+            let expected = LINES.iter().any(|&line| mask & line == line);
+            assert_eq!(is_won(mask), expected, "mask {:09b}", mask);
+        }
+    }
+
+    #[quickcheck]
+    fn conversion_round_trip(game: Game) -> bool {
+        let board = BitBoard::from(&game);
+        board.to_game().to_string() == game.to_string() && board.outcome() == game.outcome()
+    }
+
+    #[quickcheck]
+    fn same_legal_moves(rules: Rules, choices: Vec<u8>) -> bool {
+        let game = variant_game(rules, choices);
+        let board = BitBoard::from(&game);
+        legal_moves(&board) == game.legal_moves().collect::<Vec<_>>()
+            && board.outcome() == game.outcome()
+    }
+
+    #[quickcheck]
+    fn same_dead_games(rules: Rules, choices: Vec<u8>) -> bool {
+        let game = variant_game(rules, choices);
+        BitBoard::from(&game).is_dead() == game.board().is_dead()
+    }
+
+    #[quickcheck]
+    fn tracked_total_matches_evaluation(rules: Rules, seed: u8) -> bool {
+        let mut board = BitBoard::with_rules(rules);
+        let mut rng = XorShiftRng::from_seed([seed | 1; 16]);
+        let mut same = true;
+        while let Some(position) = board.random_move(&mut rng) {
+            board.make_move(position);
+            same &= board.total == board.evaluate_total();
+        }
+        same
+    }
+
+    #[quickcheck]
+    fn make_and_unmake_agree_with_game(rules: Rules, choices: Vec<u8>) -> bool {
+        let game = variant_game(rules, choices);
+        let board = BitBoard::from(&game);
+        let moves: Vec<coord::Global> = game.legal_moves().collect();
+        moves.into_iter().all(|position| {
+            let mut next_game = game.clone();
+            next_game.play(position).unwrap();
+
+            let mut next_board = board;
+            let undo = next_board.make_move(position);
+            let same = next_board == BitBoard::from(&next_game);
+
+            next_board.unmake_move(position, undo);
+            same && next_board == board
+        })
+    }
+}
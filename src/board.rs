@@ -1,8 +1,9 @@
 use coord;
-use rand::random;
+use rules::{DeadGame, DrawnRegions, FullBoard, Rules};
 use std::fmt;
 use std::fmt::Debug;
 use std::ops::{Index, IndexMut};
+use zobrist;
 
 // TODO: Token<T> { Clear(T), .. }
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -12,7 +13,7 @@ pub enum Token {
     Cross,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Player {
     Circle,
     Cross,
@@ -32,10 +33,112 @@ pub struct Local {
     pub total: Ownership,
 }
 
+/// The eight lines of a tic-tac-toe board, given as indices into its entries. These are
+/// `lines(3, 3)`, written out for speed.
+pub(crate) const LINES: [[usize; 3]; 8] = [
+    // Horizontal
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    // Vertical
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    // Diagonals
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// The squares of one of the `LINES`.
+pub(crate) fn to_locals(line: &[usize; 3]) -> [coord::Local; 3] {
+    [
+        coord::Local::from_index(line[0]),
+        coord::Local::from_index(line[1]),
+        coord::Local::from_index(line[2]),
+    ]
+}
+
+/// All rows, columns and diagonals of `k` squares on an `n` × `n` grid, given as indices
+/// of the squares counted row by row.
+pub(crate) fn lines(n: u8, k: u8) -> Vec<Vec<usize>> {
+    let (n, k) = (i32::from(n), i32::from(k));
+    let mut lines = vec![];
+    for &(dx, dy) in &[(1, 0), (0, 1), (1, 1), (-1, 1)] {
+        for y in 0..n {
+            for x in 0..n {
+                let (end_x, end_y) = (x + dx * (k - 1), y + dy * (k - 1));
+                if end_x < 0 || end_x >= n || end_y >= n {
+                    continue;
+                }
+                lines.push(
+                    (0..k)
+                        .map(|i| ((y + dy * i) * n + x + dx * i) as usize)
+                        .collect(),
+                );
+            }
+        }
+    }
+    lines
+}
+
+/// The owner of a grid of any size, given the owner of each square. The first line owned
+/// by a player in the order of the lines decides the grid. Otherwise the grid is drawn
+/// once every square is decided.
+pub(crate) fn evaluate_grid<L, F>(lines: &[L], squares: usize, owner: F) -> Ownership
+where
+    L: AsRef<[usize]>,
+    F: Fn(usize) -> Ownership,
+{
+    for line in lines {
+        let line = line.as_ref();
+        let first = owner(line[0]);
+        let is_player = first == Ownership::Circle || first == Ownership::Cross;
+        if is_player && line.iter().all(|&i| owner(i) == first) {
+            return first;
+        }
+    }
+    if (0..squares).all(|i| owner(i) != Ownership::Undecided) {
+        Ownership::Draw
+    } else {
+        Ownership::Undecided
+    }
+}
+
+/// The first line where every square belongs to the owner, who has to be a player.
+pub(crate) fn find_line<L, F>(lines: &[L], owner: Ownership, squares: F) -> Option<&L>
+where
+    L: AsRef<[usize]>,
+    F: Fn(usize) -> Ownership,
+{
+    if owner != Ownership::Circle && owner != Ownership::Cross {
+        return None;
+    }
+    lines
+        .iter()
+        .find(|line| line.as_ref().iter().all(|&i| squares(i) == owner))
+}
+
+/// Whether the token could still complete a line of the grid, because some line has no
+/// token of the other player. A complete line counts as well.
+pub(crate) fn can_complete_line<L, F>(lines: &[L], token: Token, squares: F) -> bool
+where
+    L: AsRef<[usize]>,
+    F: Fn(usize) -> Token,
+{
+    lines.iter().any(|line| {
+        line.as_ref()
+            .iter()
+            .all(|&i| squares(i) == token || squares(i) == Token::Clear)
+    })
+}
+
 #[derive(Clone, Debug)]
 pub struct Global {
     entries: [Local; 9],
     total: Ownership,
+    /// The Zobrist hash of all tokens, see `Global::hash`.
+    hash: u64,
+    rules: Rules,
 }
 
 impl Debug for Token {
@@ -83,20 +186,9 @@ impl Local {
         Local { entries, total }
     }
 
-    pub fn random() -> Self {
-        let mut result = Self::new();
-        for i in 0..9 {
-            let token = if random() {
-                Token::Cross
-            } else {
-                Token::Circle
-            };
-            result.entries[i] = token;
-        }
-        result
-    }
-
-    fn place_token(&mut self, position: coord::Local, token: Token) {
+    /// Places a token and updates the owner, which only needs to check the lines through
+    /// the new token.
+    pub fn place_token(&mut self, position: coord::Local, token: Token) {
         self[position] = token;
 
         // Check win conditions and set total Ownership
@@ -114,7 +206,7 @@ impl Local {
         let down_diag_is_won =
             self.entries[0] == token && self.entries[4] == token && self.entries[8] == token;
         let up_diag_is_won =
-            self.entries[2] == token && self.entries[4] == token && self.entries[5] == token;
+            self.entries[2] == token && self.entries[4] == token && self.entries[6] == token;
 
         // Set total ownership
         if row_is_won || col_is_won || down_diag_is_won || up_diag_is_won {
@@ -126,6 +218,63 @@ impl Local {
             self.total = Ownership::Undecided
         }
     }
+
+    /// Clears a cell and evaluates the region again.
+    pub fn remove_token(&mut self, position: coord::Local) {
+        self[position] = Token::Clear;
+        self.update_total();
+    }
+
+    fn update_total(&mut self) {
+        self.total = self.evaluate();
+    }
+
+    /// The owner of the region by its cells alone, ignoring the stored `total`. If both
+    /// players have a line, the first one in the order of rows, columns and diagonals wins.
+    pub fn evaluate(&self) -> Ownership {
+        evaluate_grid(&LINES, 9, |i| self.entries[i].into())
+    }
+
+    pub fn has_line(&self, token: Token) -> bool {
+        self.winning_line(token).is_some()
+    }
+
+    /// The first complete line of the token in the order of rows, columns and diagonals.
+    pub fn winning_line(&self, token: Token) -> Option<[coord::Local; 3]> {
+        find_line(&LINES, token.into(), |i| self.entries[i].into()).map(to_locals)
+    }
+
+    /// Whether the token could still complete a line, because some line has no token of
+    /// the other player. A complete line counts as well.
+    pub fn can_complete_line(&self, token: Token) -> bool {
+        can_complete_line(&LINES, token, |i| self.entries[i])
+    }
+
+    /// Whether neither player can complete a line anymore, so the region can only end in a
+    /// draw. This is often the case long before all nine cells are filled.
+    pub fn is_dead(&self) -> bool {
+        !self.can_complete_line(Token::Cross) && !self.can_complete_line(Token::Circle)
+    }
+
+    /// Moves the cells of the region, keeping its owner.
+    fn transform(&self, symmetry: coord::Symmetry) -> Self {
+        let mut result = self.clone();
+        for position in coord::Local::iter() {
+            result[position.transform(symmetry)] = self[position];
+        }
+        result
+    }
+
+    /// Counts the lines where the token is placed twice and the third cell is still clear.
+    pub fn count_threats(&self, token: Token) -> usize {
+        LINES
+            .iter()
+            .filter(|line| {
+                let count = |t| line.iter().filter(|&&i| self.entries[i] == t).count();
+                count(token) == 2 && count(Token::Clear) == 1
+            })
+            .count()
+    }
 }
 
 impl Index<coord::Local> for Local {
@@ -150,6 +299,11 @@ impl Default for Global {
 
 impl Global {
     pub fn new() -> Self {
+        Self::with_rules(Rules::default())
+    }
+
+    /// An empty board where the outcome is decided by the given rules.
+    pub fn with_rules(rules: Rules) -> Self {
         let entries = [
             Local::new(),
             Local::new(),
@@ -162,14 +316,70 @@ impl Global {
             Local::new(),
         ];
         let total = Ownership::Undecided;
-        Global { entries, total }
+        Global {
+            entries,
+            total,
+            hash: 0,
+            rules,
+        }
+    }
+
+    /// Builds a board from all tokens, indexed like `coord::Global`, and evaluates it.
+    pub fn from_tokens(tokens: &[Token; 81]) -> Self {
+        Self::from_tokens_and_owners(tokens, &[None; 9])
     }
 
-    pub fn random() -> Self {
+    /// Builds a board like `from_tokens`, but regions with a given owner keep it instead of
+    /// being evaluated. The tokens don't tell who captured a region first, see `set_rules`.
+    pub fn from_tokens_and_owners(tokens: &[Token; 81], owners: &[Option<Ownership>; 9]) -> Self {
         let mut result = Self::new();
-        for i in 0..9 {
-            result.entries[i] = Local::random();
+        for position in coord::Global::iter() {
+            result[position] = tokens[position.index()];
+        }
+        for (region, &owner) in result.entries.iter_mut().zip(owners.iter()) {
+            region.update_total();
+            if let Some(owner) = owner {
+                region.total = owner;
+            }
         }
+        result.update_total(Ownership::Cross);
+        result.update_hash();
+        result
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Switches to other rules and evaluates the board again from scratch.
+    ///
+    /// When tokens may be placed into decided regions, a region can contain lines of
+    /// both players. The tokens don't tell who captured it first, so the first line in
+    /// the order of rows, columns and diagonals wins. The same applies to `from_tokens`.
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+        self.evaluate();
+        self.update_hash();
+    }
+
+    fn evaluate(&mut self) {
+        let inverts = self.rules.inverts_captures();
+        for region in self.entries.iter_mut() {
+            region.update_total();
+            if inverts {
+                region.total = region.total.inverted();
+            }
+        }
+        self.update_total(Ownership::Cross);
+    }
+
+    /// Applies the symmetry to the whole board, moving regions and the cells inside them.
+    pub fn transform(&self, symmetry: coord::Symmetry) -> Self {
+        let mut result = self.clone();
+        for region in coord::Local::iter() {
+            result[region.transform(symmetry)] = self[region].transform(symmetry);
+        }
+        result.update_hash();
         result
     }
 
@@ -177,9 +387,197 @@ impl Global {
         &mut self[global_coord.get_region()][global_coord.get_local()]
     }
 
+    /// Who owns the meta-board. Anything but `Ownership::Undecided` means the game is over.
+    /// Under misère rules, the owner loses, see `game::Game::outcome`.
+    pub fn total(&self) -> Ownership {
+        self.total
+    }
+
+    /// The three regions which won the game, if any.
+    pub fn winning_line(&self) -> Option<[coord::Local; 3]> {
+        self.find_winning_line(self.total).map(to_locals)
+    }
+
+    /// The three cells which captured the region, if it is captured. Under misère rules,
+    /// this is a line of the opponent of the owner.
+    pub fn region_winning_line(&self, region: coord::Local) -> Option<[coord::Local; 3]> {
+        let local = &self[region];
+        let capturer = if self.rules.inverts_captures() {
+            local.total.inverted()
+        } else {
+            local.total
+        };
+        local.winning_line(capturer.into())
+    }
+
+    /// A Zobrist hash of the tokens on the board and the owners of the regions, which is
+    /// kept up to date by `place_token` and `remove_token`. Tokens written through `at` or
+    /// indexing aren't included until the board is rebuilt with `from_tokens`.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Places a token into a clear cell. Tokens placed into a decided region, which only
+    /// some rules allow, don't change its owner.
     pub fn place_token(&mut self, position: coord::Global, token: Token) {
-        let region: &mut Local = &mut self[position.get_region()];
-        region.place_token(position.get_local(), token);
+        let inverts = self.rules.inverts_captures();
+        let owner = self[position.get_region()].total;
+        {
+            let region: &mut Local = &mut self[position.get_region()];
+            if region.total == Ownership::Undecided {
+                region.place_token(position.get_local(), token);
+                if inverts {
+                    region.total = region.total.inverted();
+                }
+            } else {
+                region[position.get_local()] = token;
+            }
+        }
+        self.hash ^= zobrist::token(position, token);
+        self.update_owner_hash(position.get_region(), owner);
+        self.update_total(token.into());
+    }
+
+    /// Reverts `place_token` for the most recently placed token.
+    /// This relies on tokens only being placed into an undecided game.
+    pub fn remove_token(&mut self, position: coord::Global) {
+        self.hash ^= zobrist::token(position, self[position]);
+        let inverts = self.rules.inverts_captures();
+        let owner = self[position.get_region()].total;
+        {
+            let region: &mut Local = &mut self[position.get_region()];
+            region[position.get_local()] = Token::Clear;
+            // If the line which captured the region is still there, it was already
+            // captured before. Drawn regions are full, so they were undecided before the
+            // last token.
+            let line: Token = if inverts {
+                region.total.inverted().into()
+            } else {
+                region.total.into()
+            };
+            if line == Token::Clear || !region.has_line(line) {
+                region.total = Ownership::Undecided;
+            }
+        }
+        self.update_owner_hash(position.get_region(), owner);
+        self.total = Ownership::Undecided;
+    }
+
+    /// Counts the lines where two regions belong to the owner and the third is undecided.
+    pub fn count_threats(&self, owner: Ownership) -> usize {
+        LINES
+            .iter()
+            .filter(|line| {
+                let count = |o| line.iter().filter(|&&i| self.entries[i].total == o).count();
+                count(owner) == 2 && count(Ownership::Undecided) == 1
+            })
+            .count()
+    }
+
+    /// A line of regions won by the owner. Depending on the rules, drawn regions may
+    /// count for the owner as well, as long as the owner won at least one of them.
+    fn find_winning_line(&self, owner: Ownership) -> Option<&'static [usize; 3]> {
+        if owner != Ownership::Circle && owner != Ownership::Cross {
+            return None;
+        }
+        let draws_count = self.rules.drawn_regions == DrawnRegions::Both;
+        LINES.iter().find(|line| {
+            let counts = |&i: &usize| {
+                let total = self.entries[i].total;
+                total == owner || (draws_count && total == Ownership::Draw)
+            };
+            line.iter().all(counts) && line.iter().any(|&i| self.entries[i].total == owner)
+        })
+    }
+
+    fn update_hash(&mut self) {
+        let tokens = coord::Global::iter().map(|position| zobrist::token(position, self[position]));
+        let owners = coord::Local::iter().map(|region| zobrist::owner(region, self[region].total));
+        self.hash = tokens.chain(owners).fold(0, |hash, key| hash ^ key);
+    }
+
+    /// Replaces the key of the region's previous owner in the hash by the current one.
+    fn update_owner_hash(&mut self, region: coord::Local, previous: Ownership) {
+        self.hash ^= zobrist::owner(region, previous) ^ zobrist::owner(region, self[region].total);
+    }
+
+    fn update_total(&mut self, first: Ownership) {
+        self.total = self.evaluate_total(first);
+    }
+
+    /// Evaluates the meta-board, where each region counts with its own total, ignoring the
+    /// stored total. If both players have a line, the given player wins.
+    pub fn evaluate_total(&self, first: Ownership) -> Ownership {
+        let second = first.inverted();
+
+        if self.find_winning_line(first).is_some() {
+            first
+        } else if self.find_winning_line(second).is_some() {
+            second
+        } else if self
+            .entries
+            .iter()
+            .all(|local| local.total != Ownership::Undecided)
+        {
+            self.full_board_total()
+        } else if self.rules.dead_game == DeadGame::Draw && self.is_dead() {
+            Ownership::Draw
+        } else {
+            Ownership::Undecided
+        }
+    }
+
+    /// Whether the game can only end in a draw, because neither player can complete a line
+    /// of regions anymore. Undecided regions count for a player as long as the player who
+    /// would capture them for that player can still complete a line inside. Under
+    /// `FullBoard::Majority`, the region counts still decide, so the game is never dead.
+    pub fn is_dead(&self) -> bool {
+        if self.rules.full_board == FullBoard::Majority {
+            return false;
+        }
+        !self.can_complete_line(Ownership::Cross) && !self.can_complete_line(Ownership::Circle)
+    }
+
+    /// Whether the owner could still get a line of regions, like in `find_winning_line`.
+    fn can_complete_line(&self, owner: Ownership) -> bool {
+        let draws_count = self.rules.drawn_regions == DrawnRegions::Both;
+        let capturer: Token = if self.rules.inverts_captures() {
+            owner.inverted().into()
+        } else {
+            owner.into()
+        };
+        let can_win = |&i: &usize| {
+            let region = &self.entries[i];
+            region.total == owner
+                || (region.total == Ownership::Undecided && region.can_complete_line(capturer))
+        };
+        let counts = |&i: &usize| {
+            let total = self.entries[i].total;
+            // Undecided regions may still end in a draw.
+            can_win(&i) || (draws_count && total != owner.inverted())
+        };
+        LINES
+            .iter()
+            .any(|line| line.iter().all(counts) && line.iter().any(can_win))
+    }
+
+    /// The outcome of a meta-board where every region is decided, but nobody has a line.
+    fn full_board_total(&self) -> Ownership {
+        let count = |owner| {
+            self.entries
+                .iter()
+                .filter(|local| local.total == owner)
+                .count()
+        };
+        let (cross, circle) = (count(Ownership::Cross), count(Ownership::Circle));
+        if self.rules.full_board == FullBoard::Draw || cross == circle {
+            // Handle stalemate
+            Ownership::Draw
+        } else if cross > circle {
+            Ownership::Cross
+        } else {
+            Ownership::Circle
+        }
     }
 }
 
@@ -238,3 +636,328 @@ impl Player {
         }
     }
 }
+
+impl Ownership {
+    /// Swaps the players, which is how misère rules turn lines into losses.
+    pub fn inverted(self) -> Self {
+        match self {
+            Ownership::Circle => Ownership::Cross,
+            Ownership::Cross => Ownership::Circle,
+            owner => owner,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board::*;
+    use rules::Misere;
+
+    fn capture_region(board: &mut Global, region: coord::Local, token: Token) {
+        for x in 0..3 {
+            board.place_token(coord::Global::new(region, coord::Local::new(x, 0)), token);
+        }
+    }
+
+    #[test]
+    fn three_regions_in_a_row_win_the_game() {
+        let mut board = Global::new();
+        for x in 0..3 {
+            assert!(board.total() == Ownership::Undecided);
+            capture_region(&mut board, coord::Local::new(x, x), Token::Circle);
+        }
+        assert!(board.total() == Ownership::Circle);
+        assert_eq!(
+            board.winning_line(),
+            Some([
+                coord::Local::new(0, 0),
+                coord::Local::new(1, 1),
+                coord::Local::new(2, 2),
+            ])
+        );
+    }
+
+    /// Fills the region without a line.
+    fn draw_region(board: &mut Global, region: coord::Local) {
+        // X O X
+        // X O O
+        // O X X
+        let tokens = [0, 1, 0, 0, 1, 1, 1, 0, 0];
+        for local in coord::Local::iter() {
+            let token = if tokens[local.index()] == 1 {
+                Token::Circle
+            } else {
+                Token::Cross
+            };
+            board.place_token(coord::Global::new(region, local), token);
+        }
+    }
+
+    /// Captures every region without giving anybody a line, cross gets five of them.
+    fn fill_without_line(rules: Rules) -> Global {
+        // X O X
+        // X O O
+        // O X X
+        let owners = [0, 1, 0, 0, 1, 1, 1, 0, 0];
+        let mut board = Global::with_rules(rules);
+        for region in coord::Local::iter() {
+            let token = if owners[region.index()] == 1 {
+                Token::Circle
+            } else {
+                Token::Cross
+            };
+            capture_region(&mut board, region, token);
+        }
+        board
+    }
+
+    #[test]
+    fn drawn_regions_belong_to_nobody() {
+        assert!(fill_without_line(Rules::default()).total() == Ownership::Draw);
+
+        let mut board = Global::new();
+        capture_region(&mut board, coord::Local::new(0, 0), Token::Cross);
+        draw_region(&mut board, coord::Local::new(1, 0));
+        capture_region(&mut board, coord::Local::new(2, 0), Token::Cross);
+        assert!(board.total() == Ownership::Undecided);
+    }
+
+    #[test]
+    fn drawn_regions_can_count_for_both() {
+        let rules = Rules {
+            drawn_regions: DrawnRegions::Both,
+            ..Rules::default()
+        };
+        let mut board = Global::with_rules(rules);
+        capture_region(&mut board, coord::Local::new(0, 0), Token::Circle);
+        draw_region(&mut board, coord::Local::new(1, 0));
+        assert!(board.total() == Ownership::Undecided);
+        capture_region(&mut board, coord::Local::new(2, 0), Token::Circle);
+        assert!(board.total() == Ownership::Circle);
+        assert_eq!(
+            board.winning_line(),
+            Some([
+                coord::Local::new(0, 0),
+                coord::Local::new(1, 0),
+                coord::Local::new(2, 0),
+            ])
+        );
+
+        // Three drawn regions don't win for anybody.
+        let mut board = Global::with_rules(rules);
+        for x in 0..3 {
+            draw_region(&mut board, coord::Local::new(x, 1));
+        }
+        assert!(board.total() == Ownership::Undecided);
+    }
+
+    #[test]
+    fn full_board_can_go_to_majority() {
+        let rules = Rules {
+            full_board: FullBoard::Majority,
+            ..Rules::default()
+        };
+        let board = fill_without_line(rules);
+        assert!(board.total() == Ownership::Cross);
+        assert_eq!(board.winning_line(), None);
+    }
+
+    #[test]
+    fn tokens_in_decided_regions_keep_the_owner() {
+        let region = coord::Local::new(1, 1);
+        let at = |x, y| coord::Global::new(region, coord::Local::new(x, y));
+        let mut board = Global::new();
+        capture_region(&mut board, region, Token::Cross);
+        for x in 0..3 {
+            board.place_token(at(x, 1), Token::Circle);
+        }
+        assert!(board[region].total == Ownership::Cross);
+
+        // Taking back a token keeps the owner, unless it captured the region.
+        board.remove_token(at(2, 1));
+        assert!(board[region].total == Ownership::Cross);
+        board.remove_token(at(2, 0));
+        assert!(board[region].total == Ownership::Undecided);
+    }
+
+    #[test]
+    fn inverted_captures_go_to_the_opponent() {
+        let rules = Rules {
+            misere: Misere::Regions,
+            ..Rules::default()
+        };
+        let region = coord::Local::new(2, 0);
+        let mut board = Global::with_rules(rules);
+        capture_region(&mut board, region, Token::Cross);
+        assert!(board[region].total == Ownership::Circle);
+        // The line which captured the region is still the one of cross.
+        let row = [
+            coord::Local::new(0, 0),
+            coord::Local::new(1, 0),
+            coord::Local::new(2, 0),
+        ];
+        assert_eq!(board.region_winning_line(region), Some(row));
+        assert_eq!(board[region].winning_line(Token::Circle), None);
+
+        let mut tokens = [Token::Clear; 81];
+        for x in 0..3 {
+            tokens[coord::Global::new(region, coord::Local::new(x, 0)).index()] = Token::Cross;
+        }
+        let mut evaluated = Global::from_tokens(&tokens);
+        evaluated.set_rules(rules);
+        assert!(evaluated[region].total == Ownership::Circle);
+
+        board.remove_token(coord::Global::new(region, coord::Local::new(2, 0)));
+        assert!(board[region].total == Ownership::Undecided);
+    }
+
+    #[test]
+    fn count_lines() {
+        assert_eq!(lines(3, 3).len(), 8);
+        assert_eq!(lines(4, 4).len(), 10);
+        // Rows and columns have two lines each, plus four diagonals in each direction.
+        assert_eq!(lines(4, 3).len(), 24);
+        assert_eq!(lines(2, 2).len(), 6);
+    }
+
+    #[test]
+    fn standard_lines_are_generated_lines() {
+        let generated: Vec<Vec<usize>> = LINES.iter().map(|line| line.to_vec()).collect();
+        assert_eq!(generated, lines(3, 3));
+    }
+
+    #[test]
+    fn every_line_wins_a_region() {
+        for line in LINES.iter() {
+            let mut region = Local::new();
+            for &i in line {
+                region.place_token(coord::Local::from_index(i), Token::Cross);
+            }
+            assert!(region.total == Ownership::Cross, "line {:?}", line);
+        }
+    }
+
+    /// Placing tokens one by one only checks the lines through the new token, which has to
+    /// agree with evaluating the whole region. This caught the anti-diagonal checking the
+    /// wrong cell.
+    #[quickcheck]
+    fn place_token_agrees_with_evaluation(cells: Vec<u8>) -> bool {
+        let mut region = Local::new();
+        let mut token = Token::Cross;
+        for cell in cells {
+            let position = coord::Local::from_index(cell as usize % 9);
+            if region.total != Ownership::Undecided || region[position] != Token::Clear {
+                continue;
+            }
+            region.place_token(position, token);
+            let mut evaluated = region.clone();
+            evaluated.update_total();
+            if region.total != evaluated.total {
+                return false;
+            }
+            token = if token == Token::Cross {
+                Token::Circle
+            } else {
+                Token::Cross
+            };
+        }
+        true
+    }
+
+    #[test]
+    fn hash_ignores_move_order() {
+        let first = coord::Global::new(coord::Local::new(0, 0), coord::Local::new(1, 1));
+        let second = coord::Global::new(coord::Local::new(1, 1), coord::Local::new(0, 0));
+
+        let mut board = Global::new();
+        board.place_token(first, Token::Cross);
+        board.place_token(second, Token::Circle);
+        let mut other = Global::new();
+        other.place_token(second, Token::Circle);
+        other.place_token(first, Token::Cross);
+        assert_eq!(board.hash(), other.hash());
+        assert_ne!(board.hash(), Global::new().hash());
+
+        board.remove_token(second);
+        board.remove_token(first);
+        assert_eq!(board.hash(), Global::new().hash());
+    }
+
+    #[test]
+    fn regions_report_the_line_which_captured_them() {
+        let region = coord::Local::new(1, 1);
+        let at = |x, y| coord::Global::new(region, coord::Local::new(x, y));
+        let mut board = Global::new();
+        for &(x, y) in &[(2, 0), (1, 1), (0, 2)] {
+            assert_eq!(board.region_winning_line(region), None);
+            board.place_token(at(x, y), Token::Circle);
+        }
+        assert_eq!(
+            board.region_winning_line(region),
+            Some([
+                coord::Local::new(2, 0),
+                coord::Local::new(1, 1),
+                coord::Local::new(0, 2),
+            ])
+        );
+        assert_eq!(board[region].winning_line(Token::Clear), None);
+    }
+
+    #[test]
+    fn regions_die_before_they_are_full() {
+        // X O X
+        // X O O
+        // O X .
+        let tokens = [0, 1, 0, 0, 1, 1, 1, 0];
+        let mut region = Local::new();
+        for (i, &token) in tokens.iter().enumerate() {
+            let token = if token == 1 {
+                Token::Circle
+            } else {
+                Token::Cross
+            };
+            region.place_token(coord::Local::from_index(i), token);
+            // Circle can complete the bottom row until the last token.
+            assert_eq!(region.is_dead(), i == 7);
+        }
+        assert!(region.total == Ownership::Undecided);
+    }
+
+    #[test]
+    fn dead_games_can_end_early() {
+        // The regions are captured like in `fill_without_line`, except for the last one,
+        // which can't complete a line for anybody.
+        let owners = [0, 1, 0, 0, 1, 1, 1, 0];
+        let capture_all = |rules| {
+            let mut board = Global::with_rules(rules);
+            for (i, &owner) in owners.iter().enumerate() {
+                let token = if owner == 1 {
+                    Token::Circle
+                } else {
+                    Token::Cross
+                };
+                capture_region(&mut board, coord::Local::from_index(i), token);
+            }
+            board
+        };
+
+        let board = capture_all(Rules::default());
+        assert!(board.is_dead());
+        assert!(board.total() == Ownership::Undecided);
+
+        let rules = Rules {
+            dead_game: DeadGame::Draw,
+            ..Rules::default()
+        };
+        assert!(capture_all(rules).total() == Ownership::Draw);
+
+        // Counting the regions can still decide the game.
+        let rules = Rules {
+            full_board: FullBoard::Majority,
+            ..rules
+        };
+        let board = capture_all(rules);
+        assert!(!board.is_dead());
+        assert!(board.total() == Ownership::Undecided);
+    }
+}
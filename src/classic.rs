@@ -0,0 +1,223 @@
+//! Classic tic-tac-toe on a single `board::Local`, which is where new players learn the
+//! basics before moving on to the ultimate game. The game tree is small enough to search
+//! completely, so the computer opponent in `best_move` plays perfectly.
+
+use board::{self, Ownership, Player, Token};
+use coord;
+use game::IllegalMove;
+use history::History;
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: board::Local,
+    current_player: Player,
+    history: History<coord::Local>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game {
+            board: board::Local::new(),
+            current_player: Player::Cross,
+            history: History::new(),
+        }
+    }
+
+    pub fn board(&self) -> &board::Local {
+        &self.board
+    }
+
+    pub fn current_player(&self) -> Player {
+        self.current_player
+    }
+
+    /// Anything but `Ownership::Undecided` means the game is over.
+    pub fn outcome(&self) -> Ownership {
+        self.board.total
+    }
+
+    /// Checks if the current player may place a token at the given cell.
+    pub fn check(&self, position: coord::Local) -> Result<(), IllegalMove> {
+        if self.outcome() != Ownership::Undecided {
+            Err(IllegalMove::GameOver)
+        } else if self.board[position] != Token::Clear {
+            Err(IllegalMove::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// All cells the current player may place a token at.
+    pub fn legal_moves<'a>(&'a self) -> impl Iterator<Item = coord::Local> + 'a {
+        coord::Local::iter().filter(move |&position| self.check(position).is_ok())
+    }
+
+    /// All moves played so far, starting with the first one.
+    pub fn moves(&self) -> &[coord::Local] {
+        self.history.played()
+    }
+
+    /// Places a token for the current player and discards all moves which could be redone.
+    pub fn play(&mut self, position: coord::Local) -> Result<(), IllegalMove> {
+        self.check(position)?;
+        self.history.play(position);
+        self.place(position);
+
+        Ok(())
+    }
+
+    /// Takes back the last move and returns its position.
+    pub fn undo(&mut self) -> Option<coord::Local> {
+        let position = self.history.undo()?;
+
+        self.board.remove_token(position);
+        self.current_player = self.current_player.other();
+
+        Some(position)
+    }
+
+    /// Plays the last move which was taken back again and returns its position.
+    pub fn redo(&mut self) -> Option<coord::Local> {
+        let position = self.history.redo()?;
+        self.place(position);
+
+        Some(position)
+    }
+
+    fn clear_cells(&self) -> i32 {
+        coord::Local::iter()
+            .filter(|&position| self.board[position] == Token::Clear)
+            .count() as i32
+    }
+
+    fn place(&mut self, position: coord::Local) {
+        self.board.place_token(position, self.current_player.into());
+        self.current_player = self.current_player.other();
+    }
+}
+
+/// Scores of won and lost games lie between these bounds, draws score zero.
+const WIN: i32 = 10;
+
+/// A move which wins as soon as possible, or else draws, or else loses as late as possible.
+/// Equally good moves are decided by the order of `coord::Local::iter`.
+pub fn best_move(game: &Game) -> Option<coord::Local> {
+    let mut game = game.clone();
+    let moves: Vec<coord::Local> = game.legal_moves().collect();
+
+    let mut best = None;
+    let mut alpha = -WIN - 1;
+    for position in moves {
+        game.play(position).expect("Generated moves are legal.");
+        let score = -negamax(&mut game, -WIN - 1, -alpha);
+        game.undo();
+
+        if score > alpha {
+            alpha = score;
+            best = Some(position);
+        }
+    }
+    best
+}
+
+/// Scores the position from the point of view of the player to move.
+fn negamax(game: &mut Game, mut alpha: i32, beta: i32) -> i32 {
+    match game.outcome() {
+        Ownership::Undecided => {}
+        Ownership::Draw => return 0,
+        // The other player completed a line with the last move.
+        // Losing later, with fewer clear cells left, is better than losing now.
+        _ => return -1 - game.clear_cells(),
+    }
+
+    let moves: Vec<coord::Local> = game.legal_moves().collect();
+    for position in moves {
+        game.play(position).expect("Generated moves are legal.");
+        let score = -negamax(game, -beta, -alpha);
+        game.undo();
+
+        if score > alpha {
+            alpha = score;
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use classic::*;
+
+    /// Tries every answer of the opponent against the computer, which plays `computer`.
+    fn never_loses(game: &mut Game, computer: Player) -> bool {
+        let token: Token = computer.other().into();
+        if game.outcome() != Ownership::Undecided {
+            return game.outcome() != token.into();
+        }
+        if game.current_player() == computer {
+            let position = best_move(game).unwrap();
+            game.play(position).unwrap();
+            let result = never_loses(game, computer);
+            game.undo();
+            return result;
+        }
+        let moves: Vec<coord::Local> = game.legal_moves().collect();
+        moves.into_iter().all(|position| {
+            game.play(position).unwrap();
+            let result = never_loses(game, computer);
+            game.undo();
+            result
+        })
+    }
+
+    #[test]
+    fn best_move_never_loses() {
+        assert!(never_loses(&mut Game::new(), Player::Cross));
+        assert!(never_loses(&mut Game::new(), Player::Circle));
+    }
+
+    #[test]
+    fn perfect_play_is_a_draw() {
+        let mut game = Game::new();
+        while let Some(position) = best_move(&game) {
+            game.play(position).unwrap();
+        }
+        assert_eq!(game.outcome(), Ownership::Draw);
+    }
+
+    #[test]
+    fn wins_instead_of_blocking() {
+        let mut game = Game::new();
+        // X X .
+        // O O .
+        // . . .
+        for &(x, y) in &[(0, 0), (0, 1), (1, 0), (1, 1)] {
+            game.play(coord::Local::new(x, y)).unwrap();
+        }
+        assert_eq!(best_move(&game), Some(coord::Local::new(2, 0)));
+
+        // Circle could block cross, but it can win right away.
+        game.play(coord::Local::new(2, 2)).unwrap();
+        assert_eq!(best_move(&game), Some(coord::Local::new(2, 1)));
+    }
+
+    #[test]
+    fn undo_restores_the_owner() {
+        let mut game = Game::new();
+        for &(x, y) in &[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)] {
+            game.play(coord::Local::new(x, y)).unwrap();
+        }
+        assert_eq!(game.outcome(), Ownership::Cross);
+        assert_eq!(game.undo(), Some(coord::Local::new(2, 0)));
+        assert_eq!(game.outcome(), Ownership::Undecided);
+        assert_eq!(game.current_player(), Player::Cross);
+    }
+}
@@ -1,11 +1,22 @@
-use ggez::graphics::Vector2;
-
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Global(u8);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Local(u8);
 
+/// A cell in boards nested any number of levels deep, given by the square on each level
+/// from the outermost board inwards. A `Global` is a path with two squares.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path(Vec<Local>);
+
+/// One of the eight symmetries of a square: an optional reflection across the vertical
+/// axis, followed by a number of clockwise quarter turns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symmetry {
+    reflect: bool,
+    quarter_turns: u8,
+}
+
 impl Global {
     pub fn new(region: Local, local: Local) -> Self {
         Global(9 * region.0 + local.0)
@@ -20,6 +31,22 @@ impl Global {
     pub fn get_local(self) -> Local {
         Local(self.0 % 9)
     }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..81).map(Global)
+    }
+
+    /// Applies the symmetry to the region and to the cell inside it.
+    pub fn transform(self, symmetry: Symmetry) -> Self {
+        Global::new(
+            self.get_region().transform(symmetry),
+            self.get_local().transform(symmetry),
+        )
+    }
 }
 
 impl Local {
@@ -29,6 +56,11 @@ impl Local {
         Local(3 * y + x)
     }
 
+    pub fn from_index(index: usize) -> Self {
+        assert!(index < 9);
+        Local(index as u8)
+    }
+
     pub fn index(self) -> usize {
         self.0 as usize
     }
@@ -41,12 +73,110 @@ impl Local {
         self.0 / 3
     }
 
-    pub fn as_vec(self) -> Vector2 {
-        Vector2::new(self.get_x() as f32, self.get_y() as f32)
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..9).map(Local)
+    }
+
+    /// Turns the board a quarter clockwise around its center.
+    pub fn rotate(self) -> Self {
+        Local::new(2 - self.get_y(), self.get_x())
+    }
+
+    /// Mirrors the board across its vertical axis, swapping left and right.
+    pub fn reflect(self) -> Self {
+        Local::new(2 - self.get_x(), self.get_y())
+    }
+
+    pub fn transform(self, symmetry: Symmetry) -> Self {
+        let mut result = if symmetry.reflect {
+            self.reflect()
+        } else {
+            self
+        };
+        for _ in 0..symmetry.quarter_turns {
+            result = result.rotate();
+        }
+        result
+    }
+}
+
+impl Path {
+    pub fn new(squares: Vec<Local>) -> Self {
+        Path(squares)
+    }
+
+    /// The inverse of `index` for paths with `depth` squares.
+    pub fn from_index(depth: usize, index: usize) -> Self {
+        let mut squares = vec![Local(0); depth];
+        let mut rest = index;
+        for square in squares.iter_mut().rev() {
+            *square = Local((rest % 9) as u8);
+            rest /= 9;
+        }
+        assert_eq!(rest, 0);
+        Path(squares)
+    }
+
+    /// Counts the cells in the order of the squares, so each board covers a contiguous
+    /// range of indices.
+    pub fn index(&self) -> usize {
+        index(&self.0)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.0.len()
     }
 
+    pub fn squares(&self) -> &[Local] {
+        &self.0
+    }
+
+    /// All paths with `depth` squares, ordered by their index.
+    pub fn iter(depth: usize) -> impl Iterator<Item = Self> {
+        (0..9usize.pow(depth as u32)).map(move |i| Path::from_index(depth, i))
+    }
+}
+
+/// The index of a board or cell given by the squares leading to it, see `Path::index`.
+pub fn index(squares: &[Local]) -> usize {
+    squares
+        .iter()
+        .fold(0, |index, square| 9 * index + square.index())
+}
+
+impl From<Global> for Path {
+    fn from(position: Global) -> Self {
+        Path(vec![position.get_region(), position.get_local()])
+    }
+}
+
+impl Symmetry {
+    pub fn identity() -> Self {
+        Symmetry {
+            reflect: false,
+            quarter_turns: 0,
+        }
+    }
+
+    /// All eight symmetries, starting with the identity.
     pub fn iter() -> impl Iterator<Item = Self> {
-        (0..9).map(Local)
+        (0..8).map(|i| Symmetry {
+            reflect: i >= 4,
+            quarter_turns: i % 4,
+        })
+    }
+
+    /// The symmetry which undoes this one.
+    pub fn inverse(self) -> Self {
+        if self.reflect {
+            // Reflecting and turning is always a reflection, which undoes itself.
+            self
+        } else {
+            Symmetry {
+                reflect: false,
+                quarter_turns: (4 - self.quarter_turns) % 4,
+            }
+        }
     }
 }
 
@@ -61,12 +191,35 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn path_index_round_trip(squares: Vec<Local>) -> bool {
+        let squares: Vec<Local> = squares.into_iter().take(4).collect();
+        let path = Path::new(squares.clone());
+        Path::from_index(squares.len(), path.index()) == path
+    }
+
+    #[test]
+    fn global_is_a_path_of_two() {
+        for position in Global::iter() {
+            assert_eq!(Path::from(position).index(), position.index());
+        }
+    }
+
     impl Arbitrary for Global {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             Global(g.gen(): u8 % 81)
         }
     }
 
+    impl Arbitrary for Symmetry {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            Symmetry {
+                reflect: g.gen(),
+                quarter_turns: g.gen(): u8 % 4,
+            }
+        }
+    }
+
     #[quickcheck]
     fn reassemble_local_coord(local_coord: Local) -> bool {
         local_coord == Local::new(local_coord.get_x(), local_coord.get_y())
@@ -82,4 +235,31 @@ mod tests {
         let global_coord = Global::new(region_coord, local_coord);
         region_coord == global_coord.get_region() && local_coord == global_coord.get_local()
     }
+
+    #[quickcheck]
+    fn inverse_undoes_symmetry(global_coord: Global, symmetry: Symmetry) -> bool {
+        global_coord
+            .transform(symmetry)
+            .transform(symmetry.inverse())
+            == global_coord
+    }
+
+    #[test]
+    fn symmetries_are_distinct() {
+        // The top left corner and its right neighbour together tell all symmetries apart.
+        let images: Vec<(Local, Local)> = Symmetry::iter()
+            .map(|s| (Local::new(0, 0).transform(s), Local::new(1, 0).transform(s)))
+            .collect();
+        for (i, image) in images.iter().enumerate() {
+            assert!(!images[..i].contains(image));
+        }
+    }
+
+    #[test]
+    fn rotate_turns_clockwise() {
+        assert_eq!(Local::new(0, 0).rotate(), Local::new(2, 0));
+        assert_eq!(Local::new(2, 0).rotate(), Local::new(2, 2));
+        assert_eq!(Local::new(1, 1).rotate(), Local::new(1, 1));
+        assert_eq!(Local::new(0, 1).reflect(), Local::new(2, 1));
+    }
 }
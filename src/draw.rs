@@ -1,27 +1,48 @@
-use ggez::graphics::{Color, DrawMode, Drawable, Mesh, Point2, Vector2};
+use ggez::graphics::{Color, DrawMode, Drawable, Font, Mesh, Point2, Rect, Text, Vector2};
 use ggez::*;
+use std::f32::consts;
 
 use board;
-use coord;
 use measure::*;
-use MainState;
+use playable::Playable;
+use tictactoe::rules::{FreeMove, Misere, Rules};
+use tictactoe::sized::{Position, Size, Square};
+use tictactoe::{classic, coord, recursive};
+use {MainState, Mode};
+
+/// The color of winning lines, written out since `Color::from_rgb` is no constant.
+const RED: Color = Color {
+    r: 200.0 / 255.0,
+    g: 30.0 / 255.0,
+    b: 30.0 / 255.0,
+    a: 1.0,
+};
 
 /// A type to cache meshes and other information required for rendering.
 pub struct GraphicsCache {
     pub measures: Measure,
+    pub nested: NestedMeasure,
     small_circle: Mesh,
     big_circle: Mesh,
+    /// The circles filling a square on each level of the nested boards.
+    nested_circles: Vec<Mesh>,
+    /// Marks a drawn region, like `big_circle` marks one won by circle.
+    squiggle: Mesh,
+    /// The squiggles marking drawn boards on each level of the nested boards.
+    nested_squiggles: Vec<Mesh>,
+    font: Font,
     pub grid_offset: Vector2,
 }
 
 /// Several mostly constant values that only depend on the screen size.
 /// They are calculated whenever the screen size changes.
 impl GraphicsCache {
-    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+    pub fn new(ctx: &mut Context, size: Size) -> GameResult<Self> {
         let (width, _height) = graphics::get_size(ctx);
         // We only support a horizontal mode at the moment
         let height = u32::min(_height, width * 3 / 4);
-        let measures = Measure::from_size(height as f32 * 0.94, 0.02, 0.04);
+        let measures = Measure::from_size(size.n(), height as f32 * 0.94, 0.02, 0.04);
+        let nested = NestedMeasure::from_size(height as f32 * 0.94, &[0.02, 0.03, 0.04]);
 
         // Generate Meshes
         let small_circle = Mesh::new_circle(
@@ -38,6 +59,36 @@ impl GraphicsCache {
             0.5 * measures.outer.get_block_size_without_padding(),
             0.2,
         )?;
+        let nested_circles = nested
+            .levels
+            .iter()
+            .map(|level| {
+                Mesh::new_circle(
+                    ctx,
+                    DrawMode::Line(level.line_width),
+                    Point2::origin(),
+                    0.5 * level.get_block_size_without_padding(),
+                    0.2,
+                )
+            })
+            .collect::<GameResult<Vec<Mesh>>>()?;
+        let squiggle = squiggle_mesh(
+            ctx,
+            measures.outer.get_block_size_without_padding(),
+            measures.outer.line_width,
+        )?;
+        let nested_squiggles = nested
+            .levels
+            .iter()
+            .map(|level| {
+                squiggle_mesh(
+                    ctx,
+                    level.get_block_size_without_padding(),
+                    level.line_width,
+                )
+            })
+            .collect::<GameResult<Vec<Mesh>>>()?;
+        let font = Font::default_font()?;
         let grid_offset = Vector2::new(
             width as f32 - height as f32 + height as f32 * 0.03,
             height as f32 * 0.03,
@@ -45,8 +96,13 @@ impl GraphicsCache {
 
         Ok(GraphicsCache {
             measures,
+            nested,
             small_circle,
             big_circle,
+            nested_circles,
+            squiggle,
+            nested_squiggles,
+            font,
             grid_offset,
         })
     }
@@ -60,7 +116,7 @@ pub fn board(ctx: &mut Context, state: &mut MainState) -> GameResult<()> {
 
     // Draw the current player in the top left corner
     let info_offset = Vector2::new(10.0, 10.0);
-    match state.current_player {
+    match state.current_player() {
         board::Player::Cross => cross(
             ctx,
             info_offset,
@@ -75,53 +131,62 @@ pub fn board(ctx: &mut Context, state: &mut MainState) -> GameResult<()> {
         )?,
     };
 
+    let game = match state.mode {
+        Mode::Ultimate(ref game) => game.as_ref(),
+        Mode::Nested(ref game) => {
+            let mut squares = vec![];
+            return nested_board(ctx, state, game, &mut squares);
+        }
+        Mode::Classic { ref game, .. } => return classic_board(ctx, state, game),
+    };
+
+    // Misère games look just like normal ones, so they are labelled below the player.
+    let rules = game
+        .standard()
+        .map_or(Rules::default(), |game| game.rules());
+    if let Some(label) = misere_label(rules) {
+        graphics::set_color(ctx, gray)?;
+        let text = Text::new(ctx, label, &state.gfx.font)?;
+        let block_size = state.gfx.measures.inner.get_block_size_without_padding();
+        graphics::draw(
+            ctx,
+            &text,
+            Point2::origin() + info_offset + Vector2::new(0.0, block_size + 10.0),
+            0.0,
+        )?;
+    }
+
     graphics::set_color(ctx, gray)?;
     hashtag(ctx, state.gfx.grid_offset, &state.gfx.measures.outer)?;
 
-    for region in coord::Local::iter() {
+    for region in game.size().squares() {
         let region_offset =
             state.gfx.grid_offset + state.gfx.measures.outer.get_offset_with_padding(region);
 
         graphics::set_color(
             ctx,
-            determine_color(can_place_in_region(state.active_region, region), false),
+            determine_color(game.can_place_in_region(region), false),
         )?;
 
-        let board_region: &board::Local = &state.board_state[region];
-        match board_region.total {
+        match game.owner(region) {
             board::Ownership::Undecided => {
-                hashtag(ctx, region_offset, &state.gfx.measures.inner)?;
-                for local in coord::Local::iter() {
-                    let token_offset =
-                        region_offset + state.gfx.measures.inner.get_offset_with_padding(local);
-
-                    let mut token = state.board_state[region][local];
-
-                    let is_ghost = token == board::Token::Clear
-                        && state.mouse_down_position
-                            == MousePosition::Local(coord::Global::new(region, local));
-
-                    // If there is no token and the mouse is held down, we show a ghost
-                    if is_ghost {
-                        token = state.current_player.into();
-                    }
+                region_tokens(ctx, state, game, region, region_offset)?;
 
-                    graphics::set_color(
+                // Nobody can win a dead region anymore, so it is shaded.
+                if game.is_dead(region) {
+                    let block_size = state.gfx.measures.outer.get_block_size_without_padding();
+                    graphics::set_color(ctx, Color::new(0.5, 0.5, 0.5, 0.2))?;
+                    graphics::rectangle(
                         ctx,
-                        determine_color(can_place_in_region(state.active_region, region), is_ghost),
-                    )?;
-
-                    draw_token(
-                        ctx,
-                        token,
-                        &state.gfx.small_circle,
-                        token_offset,
-                        state.gfx.measures.inner.get_block_size_without_padding(),
-                        state.gfx.measures.inner.line_width,
+                        DrawMode::Fill,
+                        Rect::new(region_offset.x, region_offset.y, block_size, block_size),
                     )?;
                 }
             }
             board::Ownership::Cross => {
+                if rules.free_move == FreeMove::AnyRegion {
+                    faded_region_tokens(ctx, state, game, region, region_offset)?;
+                }
                 cross(
                     ctx,
                     region_offset,
@@ -130,6 +195,9 @@ pub fn board(ctx: &mut Context, state: &mut MainState) -> GameResult<()> {
                 )?;
             }
             board::Ownership::Circle => {
+                if rules.free_move == FreeMove::AnyRegion {
+                    faded_region_tokens(ctx, state, game, region, region_offset)?;
+                }
                 circle(
                     ctx,
                     &state.gfx.big_circle,
@@ -137,67 +205,375 @@ pub fn board(ctx: &mut Context, state: &mut MainState) -> GameResult<()> {
                     state.gfx.measures.outer.get_block_size_without_padding(),
                 )?;
             }
-            _ => {
-                // TODO: Insert some drawing, maybe a squiggle?
+            board::Ownership::Draw => {
+                // The tokens stay visible behind the squiggle, unless they are switched off.
+                if state.show_drawn_tokens {
+                    region_tokens(ctx, state, game, region, region_offset)?;
+                    graphics::set_color(ctx, gray)?;
+                }
+                squiggle(
+                    ctx,
+                    &state.gfx.squiggle,
+                    region_offset,
+                    state.gfx.measures.outer.get_block_size_without_padding(),
+                )?;
             }
         }
+
+        // Captured regions show the line which captured them across the big token.
+        if let Some(line) = game.region_winning_line(region) {
+            graphics::set_color(ctx, RED)?;
+            strike_through(ctx, region_offset, &state.gfx.measures.inner, &line)?;
+        }
+    }
+
+    let outer = &state.gfx.measures.outer;
+    let inner = &state.gfx.measures.inner;
+
+    // The last move is highlighted, so it is easy to see what the opponent just did.
+    if let Some(position) = game.last_move() {
+        let offset = state.gfx.grid_offset
+            + outer.get_offset_with_padding(position.region)
+            + inner.get_offset_with_padding(position.cell);
+        let block_size = inner.get_block_size_without_padding();
+        graphics::set_color(ctx, Color::new(1.0, 0.75, 0.0, 0.35))?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Fill,
+            Rect::new(offset.x, offset.y, block_size, block_size),
+        )?;
+    }
+
+    // Hovering a legal move outlines where the opponent will be sent, or every region they
+    // may choose from for a free move.
+    if let Some(ref preview) = state.preview {
+        let block_size = outer.get_block_size_without_padding();
+        graphics::set_color(ctx, Color::from_rgb(30, 100, 200))?;
+        for &region in &preview.regions {
+            let offset = state.gfx.grid_offset + outer.get_offset_with_padding(region);
+            graphics::rectangle(
+                ctx,
+                DrawMode::Line(inner.line_width),
+                Rect::new(offset.x, offset.y, block_size, block_size),
+            )?;
+        }
+        if preview.free_move {
+            let text = Text::new(ctx, "Free move for the opponent", &state.gfx.font)?;
+            // Below the current player and the misère label.
+            let block_size = inner.get_block_size_without_padding();
+            graphics::draw(
+                ctx,
+                &text,
+                Point2::origin() + info_offset + Vector2::new(0.0, block_size + 40.0),
+                0.0,
+            )?;
+        }
     }
 
     Ok(())
 }
 
-fn determine_color(is_active_region: bool, is_ghost: bool) -> Color {
-    match (is_active_region, is_ghost) {
-        (true, false) => Color::from_rgb(50, 50, 50),
-        (true, true) => Color::from_rgb(170, 170, 170),
-        (false, false) => Color::from_rgb(170, 170, 170),
-        (false, true) => panic!("Ghosts can only exist inside an active region."),
+/// Draws the small hashtag of a region and its tokens, with a ghost where the mouse is held
+/// down on a legal move.
+fn region_tokens(
+    ctx: &mut Context,
+    state: &MainState,
+    game: &dyn Playable,
+    region: Square,
+    region_offset: Vector2,
+) -> GameResult<()> {
+    hashtag(ctx, region_offset, &state.gfx.measures.inner)?;
+    for local in game.size().squares() {
+        let token_offset = region_offset + state.gfx.measures.inner.get_offset_with_padding(local);
+
+        let position = Position::new(region, local);
+        let mut token = game.token(position);
+
+        let is_ghost = state.mouse_down_position == MousePosition::Local(position)
+            && game.check(position).is_ok();
+
+        // If the move is legal and the mouse is held down, we show a ghost
+        if is_ghost {
+            token = game.current_player().into();
+        }
+
+        graphics::set_color(
+            ctx,
+            determine_color(game.can_place_in_region(region), is_ghost),
+        )?;
+
+        draw_token(
+            ctx,
+            token,
+            &state.gfx.small_circle,
+            token_offset,
+            state.gfx.measures.inner.get_block_size_without_padding(),
+            state.gfx.measures.inner.line_width,
+        )?;
     }
+
+    Ok(())
 }
 
-fn can_place_in_region(active_region: Option<coord::Local>, region: coord::Local) -> bool {
-    if let Some(r) = active_region {
-        r == region
-    } else {
-        // No active_region => all fields are highlighted
-        true
+/// Draws the tokens of a captured region faded, for rules which still allow moves there,
+/// and restores the color for the big token on top.
+fn faded_region_tokens(
+    ctx: &mut Context,
+    state: &MainState,
+    game: &dyn Playable,
+    region: Square,
+    region_offset: Vector2,
+) -> GameResult<()> {
+    region_tokens(ctx, state, game, region, region_offset)?;
+    let block_size = state.gfx.measures.outer.get_block_size_without_padding();
+    graphics::set_color(ctx, Color::new(1.0, 1.0, 1.0, 0.6))?;
+    graphics::rectangle(
+        ctx,
+        DrawMode::Fill,
+        Rect::new(region_offset.x, region_offset.y, block_size, block_size),
+    )?;
+    graphics::set_color(
+        ctx,
+        determine_color(game.can_place_in_region(region), false),
+    )
+}
+
+/// Draws the single board of classic tic-tac-toe as large as the meta-board.
+fn classic_board(ctx: &mut Context, state: &MainState, game: &classic::Game) -> GameResult<()> {
+    let outer = &state.gfx.measures.outer;
+
+    graphics::set_color(ctx, determine_color(true, false))?;
+    hashtag(ctx, state.gfx.grid_offset, outer)?;
+
+    for square in coord::Local::iter() {
+        let offset = state.gfx.grid_offset + outer.get_offset_with_padding(square.into());
+
+        let mut token = game.board()[square];
+        let is_ghost = state.mouse_down_position == MousePosition::Region(square.into())
+            && game.check(square).is_ok();
+
+        // If the move is legal and the mouse is held down, we show a ghost
+        if is_ghost {
+            token = game.current_player().into();
+        }
+
+        graphics::set_color(ctx, determine_color(true, is_ghost))?;
+        draw_token(
+            ctx,
+            token,
+            &state.gfx.big_circle,
+            offset,
+            outer.get_block_size_without_padding(),
+            outer.line_width,
+        )?;
     }
+
+    Ok(())
 }
 
-fn hashtag(ctx: &mut Context, offset: Vector2, hashtag_measure: &HashtagMeasure) -> GameResult<()> {
-    let c0 = 0.0;
-    let c1 = hashtag_measure.block_size + 0.5 * hashtag_measure.line_width;
-    let c2 = 2.0 * hashtag_measure.block_size + 1.5 * hashtag_measure.line_width;
-    let c3 = 3.0 * hashtag_measure.block_size + 2.0 * hashtag_measure.line_width;
+/// Draws the board reached by the squares and everything inside it, one level of nesting
+/// per call.
+fn nested_board(
+    ctx: &mut Context,
+    state: &MainState,
+    game: &recursive::Game,
+    squares: &mut Vec<coord::Local>,
+) -> GameResult<()> {
+    let level = squares.len();
+    let measure = &state.gfx.nested.levels[level];
+    let offset = state.gfx.grid_offset + state.gfx.nested.get_offset(squares);
 
-    line(
-        ctx,
-        Point2::new(c1, c0) + offset,
-        Point2::new(c1, c3) + offset,
-        hashtag_measure.line_width,
-    )?;
-    line(
+    graphics::set_color(ctx, determine_color(game.can_place_in(squares), false))?;
+    hashtag(ctx, offset, measure)?;
+
+    for square in coord::Local::iter() {
+        squares.push(square);
+        let square_offset = offset + measure.get_offset_with_padding(square.into());
+        let block_size = measure.get_block_size_without_padding();
+
+        if level + 1 == game.depth() {
+            let path = coord::Path::new(squares.clone());
+            let mut token = game.board().token(&path);
+            let is_ghost =
+                game.check(&path).is_ok() && state.mouse_down_position == MousePosition::Cell(path);
+
+            // If the move is legal and the mouse is held down, we show a ghost
+            if is_ghost {
+                token = game.current_player().into();
+            }
+
+            let is_active = game.can_place_in(&squares[..level]);
+            graphics::set_color(ctx, determine_color(is_active, is_ghost))?;
+            draw_token(
+                ctx,
+                token,
+                &state.gfx.nested_circles[level],
+                square_offset,
+                block_size,
+                measure.line_width,
+            )?;
+        } else {
+            graphics::set_color(ctx, determine_color(game.can_place_in(squares), false))?;
+            match game.board().owner(squares) {
+                board::Ownership::Undecided => nested_board(ctx, state, game, squares)?,
+                board::Ownership::Cross => {
+                    cross(ctx, square_offset, block_size, measure.line_width)?;
+                    nested_strike_through(ctx, state, game, squares)?;
+                }
+                board::Ownership::Circle => {
+                    circle(
+                        ctx,
+                        &state.gfx.nested_circles[level],
+                        square_offset,
+                        block_size,
+                    )?;
+                    nested_strike_through(ctx, state, game, squares)?;
+                }
+                board::Ownership::Draw => {
+                    if state.show_drawn_tokens {
+                        nested_board(ctx, state, game, squares)?;
+                        graphics::set_color(ctx, Color::from_rgb(50, 50, 50))?;
+                    }
+                    squiggle(
+                        ctx,
+                        &state.gfx.nested_squiggles[level],
+                        square_offset,
+                        block_size,
+                    )?
+                }
+            }
+        }
+        squares.pop();
+    }
+
+    Ok(())
+}
+
+/// Fades out the board, highlights the winning regions and announces the result.
+pub fn game_over(ctx: &mut Context, state: &mut MainState) -> GameResult<()> {
+    let fade: Color = Color::new(1.0, 1.0, 1.0, 0.7);
+    let gray: Color = Color::from_rgb(50, 50, 50);
+
+    let outer = &state.gfx.measures.outer;
+    let offset = state.gfx.grid_offset;
+
+    graphics::set_color(ctx, fade)?;
+    graphics::rectangle(
         ctx,
-        Point2::new(c2, c0) + offset,
-        Point2::new(c2, c3) + offset,
-        hashtag_measure.line_width,
+        DrawMode::Fill,
+        Rect::new(offset.x, offset.y, outer.total_size, outer.total_size),
     )?;
-    line(
+
+    if let Some(line) = state.winning_line() {
+        graphics::set_color(ctx, RED)?;
+        strike_through(ctx, offset, outer, &line)?;
+    }
+
+    let message = match state.outcome() {
+        board::Ownership::Cross => "Cross wins!",
+        board::Ownership::Circle => "Circle wins!",
+        _ => "Draw!",
+    };
+
+    graphics::set_color(ctx, gray)?;
+    let message = Text::new(ctx, message, &state.gfx.font)?;
+    let hint = Text::new(ctx, "Click or press R to play again.", &state.gfx.font)?;
+    let center_x = offset.x + 0.5 * outer.total_size;
+    let center_y = offset.y + 0.5 * outer.total_size;
+    graphics::draw(
         ctx,
-        Point2::new(c0, c1) + offset,
-        Point2::new(c3, c1) + offset,
-        hashtag_measure.line_width,
+        &message,
+        Point2::new(
+            center_x - 0.5 * message.width() as f32,
+            center_y - message.height() as f32,
+        ),
+        0.0,
     )?;
-    line(
+    graphics::draw(
         ctx,
-        Point2::new(c0, c2) + offset,
-        Point2::new(c3, c2) + offset,
-        hashtag_measure.line_width,
+        &hint,
+        Point2::new(
+            center_x - 0.5 * hint.width() as f32,
+            center_y + hint.height() as f32,
+        ),
+        0.0,
     )?;
 
     Ok(())
 }
 
+fn misere_label(rules: Rules) -> Option<&'static str> {
+    match rules.misere {
+        Misere::Off => None,
+        Misere::Game => Some("Misère: three regions in a row lose."),
+        Misere::Regions => Some("Misère: every line counts for the opponent."),
+    }
+}
+
+fn determine_color(is_active_region: bool, is_ghost: bool) -> Color {
+    match (is_active_region, is_ghost) {
+        (true, false) => Color::from_rgb(50, 50, 50),
+        (true, true) => Color::from_rgb(170, 170, 170),
+        (false, false) => Color::from_rgb(170, 170, 170),
+        (false, true) => panic!("Ghosts can only exist inside an active region."),
+    }
+}
+
+fn hashtag(ctx: &mut Context, offset: Vector2, hashtag_measure: &HashtagMeasure) -> GameResult<()> {
+    let start = 0.0;
+    let end = hashtag_measure.total_size;
+
+    for i in 1..hashtag_measure.blocks {
+        let c =
+            i as f32 * hashtag_measure.block_size + (i as f32 - 0.5) * hashtag_measure.line_width;
+        line(
+            ctx,
+            Point2::new(c, start) + offset,
+            Point2::new(c, end) + offset,
+            hashtag_measure.line_width,
+        )?;
+        line(
+            ctx,
+            Point2::new(start, c) + offset,
+            Point2::new(end, c) + offset,
+            hashtag_measure.line_width,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Strikes through the line which won the nested board reached by the squares, in the
+/// layout that board has while it is undecided.
+fn nested_strike_through(
+    ctx: &mut Context,
+    state: &MainState,
+    game: &recursive::Game,
+    squares: &[coord::Local],
+) -> GameResult<()> {
+    if let Some(line) = game.board().winning_line(squares) {
+        let line: Vec<Square> = line.iter().map(|&square| square.into()).collect();
+        let offset = state.gfx.grid_offset + state.gfx.nested.get_offset(squares);
+        graphics::set_color(ctx, RED)?;
+        strike_through(ctx, offset, &state.gfx.nested.levels[squares.len()], &line)?;
+    }
+    Ok(())
+}
+
+/// Draws a line from the center of the first square of the line to the center of the
+/// last one, on the grid at the offset.
+fn strike_through(
+    ctx: &mut Context,
+    offset: Vector2,
+    measure: &HashtagMeasure,
+    line: &[Square],
+) -> GameResult<()> {
+    let center = Vector2::new(0.5 * measure.block_size, 0.5 * measure.block_size);
+    let start = Point2::origin() + offset + measure.get_offset(line[0]) + center;
+    let stop = Point2::origin() + offset + measure.get_offset(line[line.len() - 1]) + center;
+    self::line(ctx, start, stop, measure.line_width)
+}
+
 fn draw_token(
     ctx: &mut Context,
     token: board::Token,
@@ -241,6 +617,28 @@ fn circle(ctx: &mut Context, mesh: &Mesh, offset: Vector2, block_size: f32) -> G
     Ok(())
 }
 
+fn squiggle(ctx: &mut Context, mesh: &Mesh, offset: Vector2, block_size: f32) -> GameResult<()> {
+    let center = Point2::new(0.5 * block_size, 0.5 * block_size) + offset;
+
+    mesh.draw(ctx, center, 0.0)?;
+
+    Ok(())
+}
+
+/// A wave across a square, centered on the origin like the circle meshes.
+fn squiggle_mesh(ctx: &mut Context, block_size: f32, line_width: f32) -> GameResult<Mesh> {
+    let points: Vec<Point2> = (0..33)
+        .map(|i| {
+            let t = i as f32 / 32.0;
+            Point2::new(
+                (t - 0.5) * block_size,
+                0.15 * block_size * (4.0 * consts::PI * t).sin(),
+            )
+        })
+        .collect();
+    Mesh::new_line(ctx, &points, line_width)
+}
+
 fn line(ctx: &mut Context, start: Point2, stop: Point2, width: f32) -> GameResult<()> {
     graphics::line(ctx, &[start, stop], width)
 }
@@ -0,0 +1,511 @@
+use board;
+use coord;
+use history::History;
+use rules::{FreeMove, Misere, Rules};
+use std::error::Error;
+use std::fmt;
+use zobrist;
+
+/// A game of Ultimate Tic-Tac-Toe. This enforces the rules on top of a `board::Global`
+/// and keeps track of whose turn it is and where they have to play.
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: board::Global,
+    current_player: board::Player,
+    active_region: Option<coord::Local>,
+    history: History<Turn>,
+}
+
+/// A move which has been played, together with everything required to take it back.
+#[derive(Clone, Copy, Debug)]
+struct Turn {
+    position: coord::Global,
+    active_region: Option<coord::Local>,
+}
+
+/// The reasons why a token can't be placed at some position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IllegalMove {
+    GameOver,
+    WrongRegion,
+    Occupied,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            IllegalMove::GameOver => "the game is already decided",
+            IllegalMove::WrongRegion => "the token must be placed in another region",
+            IllegalMove::Occupied => "the position is already occupied",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for IllegalMove {}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
+        Game {
+            board: board::Global::with_rules(rules),
+            current_player: board::Player::Cross,
+            active_region: None,
+            history: History::new(),
+        }
+    }
+
+    /// Continues a game from the given position, without any moves to undo.
+    /// The game is played by the rules of the board.
+    pub fn from_position(
+        board: board::Global,
+        current_player: board::Player,
+        active_region: Option<coord::Local>,
+    ) -> Self {
+        Game {
+            board,
+            current_player,
+            active_region,
+            history: History::new(),
+        }
+    }
+
+    pub fn board(&self) -> &board::Global {
+        &self.board
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.board.rules()
+    }
+
+    /// The player who places the next token.
+    pub fn current_player(&self) -> board::Player {
+        self.current_player
+    }
+
+    /// The region the current player is forced to play in.
+    /// `None` means that the current player may choose any region.
+    pub fn active_region(&self) -> Option<coord::Local> {
+        self.active_region
+    }
+
+    /// A Zobrist hash of the position, including the player to move and the active region.
+    /// Games reaching the same position by different move orders have the same hash.
+    pub fn hash(&self) -> u64 {
+        self.board.hash()
+            ^ zobrist::player(self.current_player)
+            ^ zobrist::active_region(self.active_region)
+    }
+
+    /// The position with the symmetry applied to the board and to the active region.
+    /// Like `from_position`, the result has no moves to undo.
+    pub fn transform(&self, symmetry: coord::Symmetry) -> Self {
+        Game::from_position(
+            self.board.transform(symmetry),
+            self.current_player,
+            self.active_region.map(|region| region.transform(symmetry)),
+        )
+    }
+
+    /// The smallest of the symmetric positions, together with the symmetry which leads
+    /// there from this one. Symmetric positions have the same canonical form.
+    pub fn canonical(&self) -> (Self, coord::Symmetry) {
+        coord::Symmetry::iter()
+            .map(|symmetry| (self.transform(symmetry), symmetry))
+            .min_by(|(a, _), (b, _)| a.sort_key().cmp(b.sort_key()))
+            .expect("There is always the identity.")
+    }
+
+    /// Orders positions by their tokens, in the order of `coord::Global`, and then by
+    /// the active region. Only games with the same player to move are compared.
+    fn sort_key<'a>(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let tokens = coord::Global::iter().map(move |position| match self.board[position] {
+            board::Token::Clear => 0,
+            board::Token::Cross => 1,
+            board::Token::Circle => 2,
+        });
+        let region = self
+            .active_region
+            .map_or(0, |region| region.index() as u8 + 1);
+        tokens.chain(Some(region))
+    }
+
+    /// Anything but `Ownership::Undecided` means the game is over. Under misère rules,
+    /// this is the opponent of the player who owns the meta-board.
+    pub fn outcome(&self) -> board::Ownership {
+        match self.rules().misere {
+            Misere::Off => self.board.total(),
+            Misere::Game | Misere::Regions => self.board.total().inverted(),
+        }
+    }
+
+    /// A region is playable if it is undecided and the current player is either sent there
+    /// or has a free move. Players get a free move when they are sent to a decided region.
+    /// Depending on the rules, a free move may also go into a decided region with space left.
+    pub fn can_place_in_region(&self, region: coord::Local) -> bool {
+        let is_active = self.active_region == None || self.active_region == Some(region);
+        let local = &self.board[region];
+        let still_has_space = match self.rules().free_move {
+            FreeMove::UndecidedRegion => local.total == board::Ownership::Undecided,
+            FreeMove::AnyRegion => {
+                coord::Local::iter().any(|cell| local[cell] == board::Token::Clear)
+            }
+        };
+
+        is_active && still_has_space
+    }
+
+    /// Checks if the current player may place a token at the given position.
+    /// This agrees with `legal_moves` and explains why a move is illegal.
+    pub fn check(&self, position: coord::Global) -> Result<(), IllegalMove> {
+        if self.outcome() != board::Ownership::Undecided {
+            Err(IllegalMove::GameOver)
+        } else if !self.can_place_in_region(position.get_region()) {
+            Err(IllegalMove::WrongRegion)
+        } else if self.board[position] != board::Token::Clear {
+            Err(IllegalMove::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// All positions the current player may place a token at.
+    /// There are no legal moves once the game is decided.
+    pub fn legal_moves<'a>(&'a self) -> impl Iterator<Item = coord::Global> + 'a {
+        let is_running = self.outcome() == board::Ownership::Undecided;
+        coord::Local::iter()
+            .filter(move |&region| is_running && self.can_place_in_region(region))
+            .flat_map(|region| {
+                coord::Local::iter().map(move |local| coord::Global::new(region, local))
+            })
+            .filter(move |&position| self.board[position] == board::Token::Clear)
+    }
+
+    /// All moves played so far, starting with the first one.
+    pub fn moves<'a>(&'a self) -> impl Iterator<Item = coord::Global> + 'a {
+        self.history.played().iter().map(|turn| turn.position)
+    }
+
+    /// Places a token for the current player and hands the turn to the other player.
+    /// This discards all moves which could be redone.
+    pub fn play(&mut self, position: coord::Global) -> Result<(), IllegalMove> {
+        self.check(position)?;
+        self.history.play(Turn {
+            position,
+            active_region: self.active_region,
+        });
+        self.place(position);
+
+        Ok(())
+    }
+
+    /// Takes back the last move and returns its position.
+    pub fn undo(&mut self) -> Option<coord::Global> {
+        let turn = self.history.undo()?;
+
+        self.board.remove_token(turn.position);
+        self.current_player = self.current_player.other();
+        self.active_region = turn.active_region;
+
+        Some(turn.position)
+    }
+
+    /// Plays the last move which was taken back again and returns its position.
+    pub fn redo(&mut self) -> Option<coord::Global> {
+        let turn = self.history.redo()?;
+        self.place(turn.position);
+
+        Some(turn.position)
+    }
+
+    fn place(&mut self, position: coord::Global) {
+        self.board.place_token(position, self.current_player.into());
+        self.current_player = self.current_player.other();
+        if self.board[position.get_local()].total == board::Ownership::Undecided {
+            self.active_region = Some(position.get_local());
+        } else {
+            self.active_region = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::*;
+    use generate::{self, Bias};
+    use quickcheck::{Arbitrary, Gen};
+    use rules::DeadGame;
+
+    /// A game after a random number of random legal moves.
+    impl Arbitrary for Game {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let mut game = Game::new();
+            for _ in 0..g.gen(): u8 % 81 {
+                let moves: Vec<coord::Global> = game.legal_moves().collect();
+                if moves.is_empty() {
+                    break;
+                }
+                let position = moves[g.gen(): usize % moves.len()];
+                game.play(position).unwrap();
+            }
+            game
+        }
+    }
+
+    fn at(region: (u8, u8), local: (u8, u8)) -> coord::Global {
+        coord::Global::new(
+            coord::Local::new(region.0, region.1),
+            coord::Local::new(local.0, local.1),
+        )
+    }
+
+    /// Parses a position and plays it by the given rules.
+    fn with_rules(position: &str, rules: Rules) -> Game {
+        let game: Game = position.parse().unwrap();
+        let mut board = game.board().clone();
+        board.set_rules(rules);
+        Game::from_position(board, game.current_player(), game.active_region())
+    }
+
+    /// Once nobody can complete a line anymore, the game ends in a draw however it goes on.
+    #[quickcheck]
+    fn dead_games_end_in_a_draw(rules: Rules, seed: u64) -> bool {
+        let rules = Rules {
+            dead_game: DeadGame::PlayOn,
+            ..rules
+        };
+        let finished = generate::random_game(rules, 81, Bias::Uniform, seed);
+
+        let mut game = Game::with_rules(rules);
+        let mut dead = false;
+        for position in finished.moves() {
+            dead = dead || game.board().is_dead();
+            game.play(position).unwrap();
+        }
+        !dead || game.outcome() == board::Ownership::Draw
+    }
+
+    #[test]
+    fn dead_games_can_end_early() {
+        let rules = Rules {
+            dead_game: DeadGame::Draw,
+            ..Rules::default()
+        };
+        let finished = generate::random_game(Rules::default(), 81, Bias::Uniform, 3);
+        let mut game = Game::with_rules(rules);
+        for position in finished.moves() {
+            if game.outcome() != board::Ownership::Undecided {
+                break;
+            }
+            assert!(!game.board().is_dead());
+            game.play(position).unwrap();
+        }
+        assert_eq!(game.outcome(), board::Ownership::Draw);
+        assert_eq!(
+            game.check(finished.moves().last().unwrap()),
+            Err(IllegalMove::GameOver)
+        );
+    }
+
+    #[test]
+    fn completing_a_line_loses_under_misere() {
+        let position = "XXX....../XXX....../XX......./OO......./OO......./\
+                        O......../........./........./......... X 3";
+        let rules = Rules {
+            misere: Misere::Game,
+            ..Rules::default()
+        };
+        let mut game = with_rules(position, rules);
+        game.play("3/3".parse().unwrap()).unwrap();
+        assert_eq!(game.board().total(), board::Ownership::Cross);
+        assert_eq!(game.outcome(), board::Ownership::Circle);
+    }
+
+    #[test]
+    fn free_move_can_go_into_decided_regions() {
+        // Cross captured the top left region, circle has a free move.
+        let position = "XXXOO..../........./........./........./........./\
+                        ........./........./........./......... O -";
+        let standard = with_rules(position, Rules::default());
+        assert_eq!(standard.legal_moves().count(), 72);
+        assert_eq!(
+            standard.check(at((0, 0), (2, 1))),
+            Err(IllegalMove::WrongRegion)
+        );
+
+        let mut game = with_rules(
+            position,
+            Rules {
+                free_move: FreeMove::AnyRegion,
+                ..Rules::default()
+            },
+        );
+        assert_eq!(game.legal_moves().count(), 76);
+        // Completing a line in the captured region doesn't take it over.
+        game.play(at((0, 0), (2, 1))).unwrap();
+        assert_eq!(
+            game.board()[coord::Local::new(0, 0)].total,
+            board::Ownership::Cross
+        );
+        assert_eq!(game.active_region(), Some(coord::Local::new(2, 1)));
+
+        game.undo();
+        assert_eq!(
+            game.board()[coord::Local::new(0, 0)].total,
+            board::Ownership::Cross
+        );
+        assert_eq!(game.to_string(), standard.to_string());
+    }
+
+    #[test]
+    fn first_move_is_free() {
+        assert_eq!(Game::new().legal_moves().count(), 81);
+    }
+
+    #[test]
+    fn local_position_selects_next_region() {
+        let mut game = Game::new();
+        game.play(at((0, 0), (2, 1))).unwrap();
+
+        assert_eq!(game.current_player(), board::Player::Circle);
+        assert_eq!(game.active_region(), Some(coord::Local::new(2, 1)));
+        assert_eq!(game.legal_moves().count(), 9);
+        assert_eq!(game.play(at((0, 0), (0, 0))), Err(IllegalMove::WrongRegion));
+
+        game.play(at((2, 1), (2, 1))).unwrap();
+        assert_eq!(game.play(at((2, 1), (2, 1))), Err(IllegalMove::Occupied));
+    }
+
+    #[quickcheck]
+    fn legal_moves_are_accepted(game: Game) -> bool {
+        game.legal_moves()
+            .all(|position| game.clone().play(position).is_ok())
+    }
+
+    #[quickcheck]
+    fn other_moves_are_rejected(game: Game) -> bool {
+        let moves: Vec<coord::Global> = game.legal_moves().collect();
+        coord::Global::iter()
+            .filter(|position| !moves.contains(position))
+            .all(|position| game.clone().play(position).is_err())
+    }
+
+    #[quickcheck]
+    fn undo_restores_hash(game: Game) -> bool {
+        game.legal_moves().all(|position| {
+            let mut next = game.clone();
+            next.play(position).unwrap();
+            let changed = next.hash() != game.hash();
+            next.undo();
+            changed && next.hash() == game.hash()
+        })
+    }
+
+    #[quickcheck]
+    fn hash_depends_only_on_position(game: Game) -> bool {
+        let mut tokens = [board::Token::Clear; 81];
+        for position in coord::Global::iter() {
+            tokens[position.index()] = game.board()[position];
+        }
+        let board = board::Global::from_tokens(&tokens);
+        let rebuilt = Game::from_position(board, game.current_player(), game.active_region());
+        rebuilt.hash() == game.hash()
+    }
+
+    #[quickcheck]
+    fn transform_moves_legal_moves(game: Game, symmetry: coord::Symmetry) -> bool {
+        let transformed = game.transform(symmetry);
+        let mut expected: Vec<coord::Global> = game
+            .legal_moves()
+            .map(|position| position.transform(symmetry))
+            .collect();
+        expected.sort_by_key(|position| position.index());
+        transformed.outcome() == game.outcome()
+            && transformed.legal_moves().collect::<Vec<_>>() == expected
+    }
+
+    #[quickcheck]
+    fn symmetric_games_have_same_canonical_form(game: Game, symmetry: coord::Symmetry) -> bool {
+        let (canonical, to_canonical) = game.canonical();
+        let (other, _) = game.transform(symmetry).canonical();
+        canonical.to_string() == other.to_string()
+            && canonical.to_string() == game.transform(to_canonical).to_string()
+    }
+
+    #[quickcheck]
+    fn legal_moves_stay_in_active_region(game: Game) -> bool {
+        match game.active_region() {
+            Some(region) => game.legal_moves().all(|p| p.get_region() == region),
+            None => true,
+        }
+    }
+
+    #[test]
+    fn sent_to_decided_region_is_free_move() {
+        let mut game = Game::new();
+        let moves = [
+            at((2, 2), (0, 0)),
+            at((0, 0), (0, 0)),
+            at((0, 0), (1, 0)),
+            at((1, 0), (0, 0)),
+            at((0, 0), (1, 2)),
+            at((1, 2), (0, 0)),
+            at((0, 0), (1, 1)),
+            // Circle sends Cross to the captured region
+            at((1, 1), (0, 0)),
+        ];
+        for &position in moves.iter() {
+            game.play(position).unwrap();
+        }
+
+        assert_eq!(game.active_region(), None);
+        // Eight regions with nine cells, minus the four tokens outside the captured region
+        assert_eq!(game.legal_moves().count(), 8 * 9 - 4);
+        assert!(game
+            .legal_moves()
+            .all(|p| p.get_region() != coord::Local::new(0, 0)));
+    }
+
+    #[test]
+    fn undo_reverts_region_capture() {
+        let mut game = Game::new();
+        let moves = [
+            at((1, 1), (0, 0)),
+            at((0, 0), (0, 0)),
+            at((0, 0), (1, 0)),
+            at((1, 0), (0, 0)),
+            at((0, 0), (1, 2)),
+            at((1, 2), (0, 0)),
+            at((0, 0), (1, 1)),
+        ];
+        for &position in moves.iter() {
+            game.play(position).unwrap();
+        }
+        let region = coord::Local::new(0, 0);
+        assert!(game.board()[region].total == board::Ownership::Cross);
+        assert_eq!(game.active_region(), Some(coord::Local::new(1, 1)));
+
+        assert_eq!(game.undo(), Some(moves[6]));
+        assert!(game.board()[region].total == board::Ownership::Undecided);
+        assert!(game.board()[moves[6]] == board::Token::Clear);
+        assert_eq!(game.current_player(), board::Player::Cross);
+        assert_eq!(game.active_region(), Some(region));
+
+        assert_eq!(game.redo(), Some(moves[6]));
+        assert!(game.board()[region].total == board::Ownership::Cross);
+        assert_eq!(game.redo(), None);
+
+        while game.undo().is_some() {}
+        assert_eq!(game.legal_moves().count(), 81);
+        assert_eq!(game.moves().count(), 0);
+    }
+}
@@ -0,0 +1,145 @@
+//! Seeded generation of positions which come up in real games. Unlike boards filled with
+//! random tokens, every generated position is reached by legal moves from the start, so it
+//! has consistent owners, a reachable forced region and a move list to replay.
+
+use board::{Ownership, Token};
+use coord;
+use game::Game;
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use rules::Rules;
+
+/// How the random moves are picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bias {
+    /// Every legal move is equally likely.
+    Uniform,
+    /// Skips moves which let the opponent capture a region right away, unless every move
+    /// does. The games look more like those of players who know the basics.
+    AvoidRegionLosses,
+}
+
+/// Creates the random number generator for a seed. The same seed always gives the same
+/// sequence of numbers.
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    let mut bytes = [0; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (seed >> (8 * (i % 8))) as u8;
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
+/// Plays up to `moves` random legal moves from the start and returns the game, which
+/// knows its forced region and every move played. The game ends early if it is decided.
+pub fn random_game(rules: Rules, moves: usize, bias: Bias, seed: u64) -> Game {
+    let mut rng = seeded_rng(seed);
+    let mut game = Game::with_rules(rules);
+    for _ in 0..moves {
+        let candidates = candidates(&mut game, bias);
+        if candidates.is_empty() {
+            break;
+        }
+        let position = candidates[rng.gen_range(0, candidates.len())];
+        game.play(position)
+            .expect("Only legal moves are generated.");
+    }
+    game
+}
+
+/// The moves the bias allows, which are all legal moves if it rules out every one.
+fn candidates(game: &mut Game, bias: Bias) -> Vec<coord::Global> {
+    let moves: Vec<coord::Global> = game.legal_moves().collect();
+    match bias {
+        Bias::Uniform => moves,
+        Bias::AvoidRegionLosses => {
+            let safe: Vec<coord::Global> = moves
+                .iter()
+                .cloned()
+                .filter(|&position| !gives_region_away(game, position))
+                .collect();
+            if safe.is_empty() {
+                moves
+            } else {
+                safe
+            }
+        }
+    }
+}
+
+/// Whether the opponent can capture a region with their answer to the move.
+fn gives_region_away(game: &mut Game, position: coord::Global) -> bool {
+    game.play(position)
+        .expect("Only legal moves are generated.");
+    let opponent: Ownership = Token::from(game.current_player()).into();
+    let answers: Vec<coord::Global> = game.legal_moves().collect();
+    let result = answers.into_iter().any(|answer| {
+        let region = answer.get_region();
+        let before = game.board()[region].total;
+        game.play(answer).expect("Only legal moves are generated.");
+        let after = game.board()[region].total;
+        game.undo();
+        before == Ownership::Undecided && after == opponent
+    });
+    game.undo();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use generate::*;
+    use validate::validate;
+
+    #[quickcheck]
+    fn generated_games_are_valid(rules: Rules, moves: u8, seed: u64) -> bool {
+        let game = random_game(rules, moves as usize % 100, Bias::Uniform, seed);
+        validate(&game).is_empty()
+    }
+
+    #[quickcheck]
+    fn plays_the_requested_number_of_moves(moves: u8, seed: u64) -> bool {
+        let moves = moves as usize % 100;
+        let game = random_game(Rules::default(), moves, Bias::Uniform, seed);
+        let played = game.moves().count();
+        played == moves || (played < moves && game.outcome() != Ownership::Undecided)
+    }
+
+    #[quickcheck]
+    fn biased_games_avoid_region_losses(seed: u64) -> bool {
+        let game = random_game(Rules::default(), 40, Bias::AvoidRegionLosses, seed);
+
+        // Replay the game and check every move against the safe moves of its position.
+        let moves: Vec<coord::Global> = game.moves().collect();
+        let mut replay = Game::new();
+        moves.into_iter().all(|position| {
+            let safe = candidates(&mut replay, Bias::AvoidRegionLosses);
+            let result = safe.contains(&position);
+            replay.play(position).unwrap();
+            result
+        })
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let first = random_game(Rules::default(), 30, Bias::Uniform, 5);
+        let second = random_game(Rules::default(), 30, Bias::Uniform, 5);
+        assert!(first.moves().eq(second.moves()));
+        assert_eq!(first.active_region(), second.active_region());
+
+        let other = random_game(Rules::default(), 30, Bias::Uniform, 6);
+        assert!(!first.moves().eq(other.moves()));
+    }
+
+    #[test]
+    fn avoids_sending_into_a_threat() {
+        // Circle threatens the top left region, so cross shouldn't send it there, neither
+        // directly nor with a free move after capturing the center region.
+        let mut game: Game = "OO......./........./........./........./...X.X.../\
+                              ........./........./........./......... X 5"
+            .parse()
+            .unwrap();
+        let safe = candidates(&mut game, Bias::AvoidRegionLosses);
+        assert_eq!(safe.len(), 5);
+        assert!(!safe.contains(&"5/1".parse().unwrap()));
+        assert!(!safe.contains(&"5/5".parse().unwrap()));
+    }
+}
@@ -0,0 +1,89 @@
+//! The moves of a game, which can be taken back and played again. Every game keeps its
+//! moves in a `History`, storing whatever it needs to take a move back.
+
+/// The moves played so far, and the moves which were taken back and can be redone.
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    played: Vec<T>,
+    /// The most recently undone move comes last.
+    undone: Vec<T>,
+}
+
+impl<T: Clone> Default for History<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> History<T> {
+    pub fn new() -> Self {
+        History {
+            played: vec![],
+            undone: vec![],
+        }
+    }
+
+    /// All moves played so far, starting with the first one.
+    pub fn played(&self) -> &[T] {
+        &self.played
+    }
+
+    /// The move played last.
+    pub fn last(&self) -> Option<&T> {
+        self.played.last()
+    }
+
+    /// Records a new move, which discards all moves which could be redone.
+    pub fn play(&mut self, turn: T) {
+        self.played.push(turn);
+        self.undone.clear();
+    }
+
+    /// Takes back the last move and returns it, so the game can revert it.
+    pub fn undo(&mut self) -> Option<T> {
+        let turn = self.played.pop()?;
+        self.undone.push(turn.clone());
+        Some(turn)
+    }
+
+    /// Plays the last move which was taken back again and returns it, so the game can
+    /// apply it. Moves which were taken back are always legal again.
+    pub fn redo(&mut self) -> Option<T> {
+        let turn = self.undone.pop()?;
+        self.played.push(turn.clone());
+        Some(turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use history::*;
+
+    #[quickcheck]
+    fn undo_and_redo_restore_the_moves(moves: Vec<u8>, steps: u8) -> bool {
+        let mut history = History::new();
+        for &turn in &moves {
+            history.play(turn);
+        }
+        let steps = steps as usize % (moves.len() + 1);
+        let undone: Vec<u8> = (0..steps).filter_map(|_| history.undo()).collect();
+        let redone: Vec<u8> = (0..steps).filter_map(|_| history.redo()).collect();
+
+        undone.iter().rev().eq(&moves[moves.len() - steps..])
+            && redone.iter().eq(&moves[moves.len() - steps..])
+            && history.played() == &moves[..]
+            && history.redo().is_none()
+    }
+
+    #[test]
+    fn new_moves_discard_undone_ones() {
+        let mut history = History::new();
+        history.play(1);
+        history.play(2);
+        assert_eq!(history.undo(), Some(2));
+        history.play(3);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.played(), &[1, 3]);
+        assert_eq!(history.last(), Some(&3));
+    }
+}
@@ -0,0 +1,31 @@
+//! The rules of Ultimate Tic-Tac-Toe without any graphics dependency.
+//! The `gui` feature adds the `ggez` frontend as a binary on top of this library.
+
+#![feature(type_ascription)]
+#![cfg_attr(test, feature(plugin))]
+#![cfg_attr(test, plugin(quickcheck_macros))]
+#![warn(clippy)]
+#![allow(cast_lossless)]
+
+#[cfg(test)]
+extern crate quickcheck;
+extern crate rand;
+
+pub mod ai;
+pub mod bitboard;
+pub mod board;
+pub mod classic;
+pub mod coord;
+pub mod game;
+pub mod generate;
+pub mod history;
+pub mod mcts;
+pub mod notation;
+pub mod perft;
+pub mod record;
+pub mod recursive;
+pub mod rules;
+pub mod sized;
+pub mod transposition;
+pub mod validate;
+pub mod zobrist;
@@ -1,79 +1,309 @@
 #![feature(type_ascription)]
-#![feature(plugin)]
-#![plugin(quickcheck_macros)]
+#![cfg_attr(test, feature(plugin))]
+#![cfg_attr(test, plugin(quickcheck_macros))]
 #![warn(clippy)]
 #![allow(cast_lossless)]
 
 extern crate ggez;
+#[cfg(test)]
 extern crate quickcheck;
-extern crate rand;
-use ggez::event::MouseButton;
+extern crate tictactoe;
+use ggez::event::{Keycode, Mod, MouseButton, MouseState};
 use ggez::graphics::Point2;
 use ggez::*;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, fs, process};
 
-mod board;
-mod coord;
 mod draw;
 mod measure;
+mod playable;
+
+use tictactoe::{ai, board, classic, coord, game, mcts, recursive};
 
 use draw::GraphicsCache;
 use measure::MousePosition;
+use playable::{NextRegions, Playable};
+use tictactoe::record::{self, Record};
+use tictactoe::rules::Rules;
+use tictactoe::sized::{Size, SizeError, Square};
+
+/// The file used by the save and load shortcuts.
+const SAVE_FILE: &str = "savegame.txt";
+
+/// The computer opponent always plays circle, so the human moves first.
+const OPPONENT: board::Player = board::Player::Circle;
+
+/// The game being played. Only the ultimate game consists of regions of cells, so the
+/// other modes don't fit `Playable`.
+pub enum Mode {
+    /// Ultimate Tic-Tac-Toe on any size and by any rules.
+    Ultimate(Box<dyn Playable>),
+    /// The nested boards of Ultimate Ultimate.
+    Nested(recursive::Game),
+    /// A single board, optionally against a computer which plays perfectly.
+    Classic {
+        game: classic::Game,
+        against_computer: bool,
+    },
+}
 
 pub struct MainState {
-    pub board_state: board::Global,
-    pub current_player: board::Player,
-    pub active_region: Option<coord::Local>,
+    /// The size of new games.
+    pub size: Size,
+    /// The rules for new games.
+    pub rules: Rules,
+    pub mode: Mode,
+    /// Searches on its own thread, so the window stays responsive while it thinks.
+    pub opponent: Option<ai::Worker>,
     pub active_hover: MousePosition,
+    /// Where the hovered move sends the opponent, kept until the mouse or the game changes.
+    pub preview: Option<NextRegions>,
     pub mouse_down_position: MousePosition,
+    /// Whether drawn regions still show their tokens behind the squiggle.
+    pub show_drawn_tokens: bool,
     pub gfx: GraphicsCache,
 }
 
 impl MainState {
-    fn new(ctx: &mut Context) -> GameResult<MainState> {
-        let board_state = board::Global::new();
-        let current_player = board::Player::Cross;
-        let gfx = GraphicsCache::new(ctx)?;
+    fn new(ctx: &mut Context, options: Options) -> GameResult<MainState> {
+        let gfx = GraphicsCache::new(ctx, options.size)?;
+        let (size, rules) = (options.size, options.rules);
+        let mode = options
+            .mode
+            .unwrap_or_else(|| Mode::Ultimate(playable::new_game(size, rules)));
         let s = MainState {
-            board_state,
-            current_player,
-            active_region: None,
+            size: options.size,
+            rules: options.rules,
+            mode,
+            opponent: options.opponent.map(ai::Worker::new),
             active_hover: MousePosition::Outside,
+            preview: None,
             mouse_down_position: MousePosition::Outside,
+            show_drawn_tokens: true,
             gfx,
         };
         Ok(s)
     }
 
-    pub fn can_place_in_region(&mut self, region: coord::Local) -> bool {
-        let is_active = self.active_region == None || self.active_region == Some(region);
-        let still_has_space = self.board_state[region].total == board::Ownership::Undecided;
+    /// Starts a new game, keeping the graphics state.
+    pub fn restart(&mut self) {
+        match self.mode {
+            Mode::Ultimate(ref mut game) => *game = playable::new_game(self.size, self.rules),
+            Mode::Nested(ref mut game) => *game = recursive::Game::default(),
+            Mode::Classic { ref mut game, .. } => *game = classic::Game::new(),
+        }
+        self.mouse_down_position = MousePosition::Outside;
+        self.update_preview();
+    }
+
+    /// Against the computer, this also takes back its answer so the human can move again.
+    pub fn undo(&mut self) {
+        let against_computer = self.opponent.is_some();
+        match self.mode {
+            Mode::Ultimate(ref mut game) => {
+                game.undo();
+                while against_computer && game.current_player() == OPPONENT && game.undo() {}
+            }
+            Mode::Nested(ref mut game) => {
+                game.undo();
+            }
+            Mode::Classic {
+                ref mut game,
+                against_computer,
+            } => {
+                game.undo();
+                while against_computer && game.current_player() == OPPONENT && game.undo().is_some()
+                {
+                }
+            }
+        }
+        self.mouse_down_position = MousePosition::Outside;
+        self.update_preview();
+    }
+
+    pub fn redo(&mut self) {
+        let against_computer = self.opponent.is_some();
+        match self.mode {
+            Mode::Ultimate(ref mut game) => {
+                game.redo();
+                if against_computer && game.current_player() == OPPONENT {
+                    game.redo();
+                }
+            }
+            Mode::Nested(ref mut game) => {
+                game.redo();
+            }
+            Mode::Classic {
+                ref mut game,
+                against_computer,
+            } => {
+                game.redo();
+                if against_computer && game.current_player() == OPPONENT {
+                    game.redo();
+                }
+            }
+        }
+        self.mouse_down_position = MousePosition::Outside;
+        self.update_preview();
+    }
+
+    fn is_opponents_turn(&self) -> bool {
+        let has_opponent = match self.mode {
+            Mode::Ultimate(_) => self.opponent.is_some(),
+            Mode::Nested(_) => false,
+            Mode::Classic {
+                against_computer, ..
+            } => against_computer,
+        };
+        has_opponent && self.current_player() == OPPONENT
+    }
+
+    pub fn save(&self) {
+        let standard = match self.mode {
+            Mode::Ultimate(ref game) => game.standard(),
+            _ => None,
+        };
+        let record = match standard {
+            Some(game) => {
+                let circle = if self.opponent.is_some() {
+                    "Computer"
+                } else {
+                    "Human"
+                };
+                let today = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                Record::from_game(game, "Human", circle, &record::format_date(today))
+            }
+            _ => {
+                eprintln!("Only standard games can be saved.");
+                return;
+            }
+        };
+        match fs::write(SAVE_FILE, record.to_string()) {
+            Ok(()) => println!("Saved the game to {}.", SAVE_FILE),
+            Err(e) => eprintln!("Could not save the game to {}: {}", SAVE_FILE, e),
+        }
+    }
 
-        is_active && still_has_space
+    /// Saved games always have the standard size, so this switches back to it.
+    pub fn load(&mut self, ctx: &mut Context) {
+        match load_game(SAVE_FILE) {
+            Ok(game) => {
+                // Saved games always have the standard size, which needs other graphics.
+                if self.size != Size::default() {
+                    match GraphicsCache::new(ctx, Size::default()) {
+                        Ok(gfx) => self.gfx = gfx,
+                        Err(e) => {
+                            eprintln!("Could not show the game from {}: {}", SAVE_FILE, e);
+                            return;
+                        }
+                    }
+                    self.size = Size::default();
+                }
+                // Restarting keeps the rules of the loaded game.
+                self.rules = game.rules();
+                self.mode = Mode::Ultimate(Box::new(game));
+                self.mouse_down_position = MousePosition::Outside;
+                self.update_preview();
+            }
+            Err(e) => eprintln!("Could not load the game from {}: {}", SAVE_FILE, e),
+        }
     }
 
-    fn on_try_place_token(&mut self, position: coord::Global) {
-        let correct_region = self.can_place_in_region(position.get_region());
-        let position_is_empty = self.board_state[position] == board::Token::Clear;
+    pub fn current_player(&self) -> board::Player {
+        match self.mode {
+            Mode::Ultimate(ref game) => game.current_player(),
+            Mode::Nested(ref game) => game.current_player(),
+            Mode::Classic { ref game, .. } => game.current_player(),
+        }
+    }
 
-        if correct_region && position_is_empty {
-            self.on_place_token(position);
+    pub fn outcome(&self) -> board::Ownership {
+        match self.mode {
+            Mode::Ultimate(ref game) => game.outcome(),
+            Mode::Nested(ref game) => game.outcome(),
+            Mode::Classic { ref game, .. } => game.outcome(),
         }
     }
 
-    fn on_place_token(&mut self, position: coord::Global) {
-        self.board_state
-            .place_token(position, self.current_player.into());
-        self.current_player = self.current_player.other();
-        if self.board_state[position.get_local()].total == board::Ownership::Undecided {
-            self.active_region = Some(position.get_local());
-        } else {
-            self.active_region = None;
+    /// The squares of the outermost board which won the game, if any.
+    pub fn winning_line(&self) -> Option<Vec<Square>> {
+        match self.mode {
+            Mode::Ultimate(ref game) => game.winning_line(),
+            Mode::Nested(ref game) => game
+                .board()
+                .winning_line(&[])
+                .map(|line| line.iter().map(|&square| square.into()).collect()),
+            Mode::Classic { ref game, .. } => {
+                let board = game.board();
+                board
+                    .winning_line(board.total.into())
+                    .map(|line| line.iter().map(|&square| square.into()).collect())
+            }
+        }
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.outcome() != board::Ownership::Undecided
+    }
+
+    /// Previews the hovered move again, after the mouse moved or the game changed.
+    fn update_preview(&mut self) {
+        self.preview = match (&self.active_hover, &self.mode) {
+            (&MousePosition::Local(position), &Mode::Ultimate(ref game)) => {
+                game.next_regions(position)
+            }
+            _ => None,
+        };
+    }
+
+    /// Finds what is under the mouse, in the layout of the game on the screen.
+    fn resolve_mouse_position(&self, x: i32, y: i32) -> MousePosition {
+        let pos = Point2::new(x as f32, y as f32) - self.gfx.grid_offset;
+        match self.mode {
+            Mode::Ultimate(_) => self.gfx.measures.resolve_mouse_position(pos),
+            Mode::Nested(_) => self.gfx.nested.resolve_mouse_position(pos),
+            // The single board is drawn at the size of the regions.
+            Mode::Classic { .. } => match self.gfx.measures.resolve_mouse_position(pos) {
+                MousePosition::Local(position) => MousePosition::Region(position.region),
+                position => position,
+            },
         }
     }
 }
 
 impl event::EventHandler for MainState {
     fn update(&mut self, _ctx: &mut Context) -> GameResult<()> {
+        if self.is_opponents_turn() && !self.is_game_over() {
+            if let Mode::Classic { ref mut game, .. } = self.mode {
+                if let Some(position) = classic::best_move(game) {
+                    game.play(position)
+                        .expect("The computer opponent only plays legal moves.");
+                }
+                return Ok(());
+            }
+            if let Mode::Ultimate(ref mut game) = self.mode {
+                let choice = match (self.opponent.as_mut(), game.standard()) {
+                    (Some(opponent), Some(standard)) => opponent.poll(standard),
+                    _ => Ok(None),
+                };
+                match choice {
+                    Ok(Some(position)) => game
+                        .play(position.into())
+                        .expect("The computer opponent only plays legal moves."),
+                    Ok(None) => return Ok(()),
+                    Err(e) => {
+                        // Without its opponent, the human moves for both players.
+                        eprintln!("{}, so you play on for both sides", e);
+                        self.opponent = None;
+                    }
+                }
+            }
+            self.update_preview();
+        }
         Ok(())
     }
 
@@ -82,6 +312,10 @@ impl event::EventHandler for MainState {
 
         draw::board(ctx, self)?;
 
+        if self.is_game_over() {
+            draw::game_over(ctx, self)?;
+        }
+
         graphics::present(ctx);
 
         Ok(())
@@ -97,8 +331,7 @@ impl event::EventHandler for MainState {
         use ggez::event::MouseButton;
         if _button == MouseButton::Left {
             // Get click position in game terms and store it for the release
-            let rel_mouse_position = Point2::new(_x as f32, _y as f32) - self.gfx.grid_offset;
-            self.mouse_down_position = self.gfx.measures.resolve_mouse_position(rel_mouse_position);
+            self.mouse_down_position = self.resolve_mouse_position(_x, _y);
         } else if _button == MouseButton::Right {
             self.mouse_down_position = MousePosition::Outside;
         }
@@ -111,8 +344,20 @@ impl event::EventHandler for MainState {
         _x: i32,
         _y: i32,
     ) {
-        let rel_mouse_position = Point2::new(_x as f32, _y as f32) - self.gfx.grid_offset;
-        let click = self.gfx.measures.resolve_mouse_position(rel_mouse_position);
+        if self.is_game_over() {
+            // Any click on the game over screen starts a new game.
+            if _button == MouseButton::Left {
+                self.restart();
+            }
+            return;
+        }
+        if self.is_opponents_turn() {
+            // The computer is still thinking about its move.
+            self.mouse_down_position = MousePosition::Outside;
+            return;
+        }
+
+        let click = self.resolve_mouse_position(_x, _y);
 
         if click != self.mouse_down_position {
             // Click position tracking indicates that the user wants to cancel the operation.
@@ -120,15 +365,59 @@ impl event::EventHandler for MainState {
             return;
         }
 
-        if let MousePosition::Local(coord) = click {
-            self.on_try_place_token(coord);
+        // Illegal moves are simply ignored.
+        match (click, &mut self.mode) {
+            (MousePosition::Local(coord), &mut Mode::Ultimate(ref mut game)) => {
+                let _ = game.play(coord);
+            }
+            (MousePosition::Cell(path), &mut Mode::Nested(ref mut game)) => {
+                let _ = game.play(path);
+            }
+            (MousePosition::Region(square), &mut Mode::Classic { ref mut game, .. }) => {
+                let _ = game.play(coord::Local::new(square.x, square.y));
+            }
+            _ => (),
         }
 
         self.mouse_down_position = MousePosition::Outside;
+        self.update_preview();
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        _state: MouseState,
+        x: i32,
+        y: i32,
+        _xrel: i32,
+        _yrel: i32,
+    ) {
+        // The hovered cell previews where the opponent will be sent.
+        let hover = self.resolve_mouse_position(x, y);
+        if hover != self.active_hover {
+            self.active_hover = hover;
+            self.update_preview();
+        }
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, _repeat: bool) {
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        match keycode {
+            Keycode::Escape => ctx.quit().unwrap(),
+            Keycode::R => self.restart(),
+            Keycode::T => self.show_drawn_tokens = !self.show_drawn_tokens,
+            Keycode::Z if ctrl && shift => self.redo(),
+            Keycode::Z if ctrl => self.undo(),
+            Keycode::Y if ctrl => self.redo(),
+            Keycode::S if ctrl => self.save(),
+            Keycode::O if ctrl => self.load(ctx),
+            _ => (),
+        }
     }
 
     fn resize_event(&mut self, ctx: &mut Context, width: u32, height: u32) {
-        match GraphicsCache::new(ctx) {
+        match GraphicsCache::new(ctx, self.size) {
             Ok(gfx) => self.gfx = gfx,
             Err(e) => panic!("Error while resizing: {:?}", e),
         }
@@ -136,15 +425,159 @@ impl event::EventHandler for MainState {
         graphics::set_screen_coordinates(
             ctx,
             ggez::graphics::Rect::new(0.0, 0.0, width as f32, height as f32),
-        ).unwrap();
+        )
+        .unwrap();
+    }
+}
+
+/// Reads a game record and replays it, which validates every move.
+fn load_game(path: &str) -> Result<game::Game, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let record: Record = text.parse()?;
+    Ok(record.replay()?)
+}
+
+const USAGE: &str = "Usage: tictactoe [--ai DEPTH | --mcts MILLISECONDS] [--rules RULES]
+       tictactoe [--size N [--line K]]
+       tictactoe --nested
+       tictactoe --classic [--perfect]
+
+Options:
+    --ai DEPTH            Play against a minimax search, which looks DEPTH moves ahead.
+    --mcts MILLISECONDS   Play against a Monte Carlo tree search, which thinks as long
+                          as given for each move.
+    --rules RULES         Play by other rules, given as a comma separated list of
+                          free-move-anywhere   A free move may go into decided regions.
+                          draws-for-both       Drawn regions count for both players.
+                          majority             If all regions are decided without a
+                                               line, most regions win.
+                          misere               Completing three regions in a row
+                                               loses the game.
+                          misere-regions       Lines inside regions count for the
+                                               opponent as well.
+                          early-draw           The game ends as a draw as soon as
+                                               nobody can complete a line anymore.
+    --size N              Play on N x N regions of N x N cells, for N from 2 to 5.
+    --line K              Capture regions and win with K in a row instead of N.
+                          Defaults to 3 on larger boards. Other sizes are played
+                          by the standard rules and without a computer opponent.
+    --nested              Play Ultimate Ultimate Tic-Tac-Toe, where every cell of
+                          every region is a board of its own.
+    --classic             Play plain tic-tac-toe on a single board.
+    --perfect             Play the single board against a computer which never
+                          loses.";
+
+/// Settings chosen on the command line.
+struct Options {
+    opponent: Option<Box<dyn ai::Bot + Send>>,
+    rules: Rules,
+    size: Size,
+    /// `None` for the ultimate game, which is created once the size and rules are known.
+    mode: Option<Mode>,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut options = Options {
+            opponent: None,
+            rules: Rules::default(),
+            size: Size::default(),
+            mode: None,
+        };
+        let mut perfect = false;
+        let mut n = None;
+        let mut k = None;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ai" => {
+                    let depth = parse_value(&mut args, &arg)?;
+                    options.opponent = Some(Box::new(ai::AlphaBeta::new(depth)));
+                }
+                "--mcts" => {
+                    let millis = parse_value(&mut args, &arg)?;
+                    let budget = mcts::Budget::Time(Duration::from_millis(millis));
+                    options.opponent = Some(Box::new(mcts::Mcts::new(budget, random_seed())));
+                }
+                "--rules" => options.rules = parse_value(&mut args, &arg)?,
+                "--size" => n = Some(parse_value(&mut args, &arg)?),
+                "--line" => k = Some(parse_value(&mut args, &arg)?),
+                "--nested" => options.mode = Some(Mode::Nested(recursive::Game::default())),
+                "--classic" => {
+                    options.mode = Some(Mode::Classic {
+                        game: classic::Game::new(),
+                        against_computer: false,
+                    })
+                }
+                "--perfect" => perfect = true,
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if let Some(n) = n {
+            let k = k.unwrap_or_else(|| u8::min(n, 3));
+            options.size = Size::new(n, k).map_err(|e: SizeError| e.to_string())?;
+        } else if k.is_some() {
+            return Err("--line requires --size".to_string());
+        }
+        if let Some(Mode::Classic {
+            ref mut against_computer,
+            ..
+        }) = options.mode
+        {
+            *against_computer = perfect;
+        } else if perfect {
+            return Err("--perfect requires --classic".to_string());
+        }
+        if options.mode.is_some()
+            && (options.opponent.is_some() || options.rules != Rules::default() || n.is_some())
+        {
+            return Err("--nested and --classic can't be combined with other options".to_string());
+        }
+        if options.size != Size::default() {
+            if options.opponent.is_some() {
+                return Err("computer opponents only play on the standard size".to_string());
+            }
+            if options.rules != Rules::default() {
+                return Err("rule variants only work on the standard size".to_string());
+            }
+        }
+
+        Ok(options)
     }
 }
 
+/// Reads the value following a flag.
+fn parse_value<T: FromStr>(
+    args: &mut dyn Iterator<Item = String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+fn random_seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() ^ u64::from(now.subsec_nanos())
+}
+
 pub fn main() {
+    let options = Options::from_args().unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
     let mut c = conf::Conf::new();
     c.window_setup.resizable = true;
     let ctx = &mut Context::load_from_conf("super_simple", "ggez", c).unwrap();
-    let state = &mut MainState::new(ctx).unwrap();
+    let state = &mut MainState::new(ctx, options).unwrap();
 
     event::run(ctx, state).unwrap();
 }
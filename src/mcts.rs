@@ -0,0 +1,327 @@
+//! A Monte Carlo tree search (UCT) engine with random playouts.
+
+use ai::Bot;
+use bitboard::BitBoard;
+use board;
+use coord;
+use game::Game;
+use generate;
+use rand::prng::XorShiftRng;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// How long a single search may run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+/// How often a move was explored and how well it did for the player making it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStatistics {
+    pub position: coord::Global,
+    pub visits: u32,
+    /// Wins count as 1 and draws as 0.5.
+    pub score: f64,
+}
+
+/// The result of a search. The moves are sorted by visits, the most visited first.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best: Option<coord::Global>,
+    pub moves: Vec<MoveStatistics>,
+    /// The number of playouts in the tree, including those reused from earlier searches.
+    pub playouts: u32,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    /// The move leading to this node, `None` for the root.
+    position: Option<coord::Global>,
+    /// The player who made that move.
+    player: board::Player,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<coord::Global>,
+    visits: u32,
+    score: f64,
+}
+
+/// The search tree with its root position. Nodes refer to each other by index.
+#[derive(Clone, Debug)]
+struct Tree {
+    game: Game,
+    nodes: Vec<Node>,
+}
+
+/// A Monte Carlo tree search which keeps the explored subtree between moves.
+#[derive(Clone, Debug)]
+pub struct Mcts {
+    pub budget: Budget,
+    /// Larger values explore rarely visited moves more often.
+    pub exploration: f64,
+    rng: XorShiftRng,
+    tree: Option<Tree>,
+}
+
+impl Node {
+    fn new(position: Option<coord::Global>, parent: Option<usize>, game: &Game) -> Self {
+        Node {
+            position,
+            player: game.current_player().other(),
+            parent,
+            children: vec![],
+            untried: game.legal_moves().collect(),
+            visits: 0,
+            score: 0.0,
+        }
+    }
+
+    fn statistics(&self) -> MoveStatistics {
+        MoveStatistics {
+            position: self.position.expect("Only the root has no move."),
+            visits: self.visits,
+            score: self.score,
+        }
+    }
+}
+
+impl Tree {
+    fn new(game: &Game) -> Self {
+        Tree {
+            game: game.clone(),
+            nodes: vec![Node::new(None, None, game)],
+        }
+    }
+
+    /// Follows the moves played since the tree was built and keeps only that subtree.
+    /// Returns `None` if the game didn't continue from the root position.
+    fn advance(mut self, game: &Game) -> Option<Self> {
+        let known = self.game.moves().count();
+        let moves: Vec<coord::Global> = game.moves().collect();
+        if moves.len() < known || !self.game.moves().eq(moves[..known].iter().cloned()) {
+            return None;
+        }
+
+        let mut root = 0;
+        for &position in &moves[known..] {
+            root = *self.nodes[root]
+                .children
+                .iter()
+                .find(|&&child| self.nodes[child].position == Some(position))?;
+            self.game.play(position).ok()?;
+        }
+        if self.game.to_string() != game.to_string() {
+            return None;
+        }
+
+        Some(self.subtree(root))
+    }
+
+    /// Copies the subtree below the given node into a new tree.
+    fn subtree(self, root: usize) -> Self {
+        let mut nodes = Vec::new();
+        let mut stack = vec![(root, None)];
+        while let Some((old, parent)) = stack.pop() {
+            let new = nodes.len();
+            let mut node = self.nodes[old].clone();
+            node.parent = parent;
+            node.children.clear();
+            nodes.push(node);
+            if let Some(parent) = parent {
+                nodes[parent].children.push(new);
+            }
+            for &child in &self.nodes[old].children {
+                stack.push((child, Some(new)));
+            }
+        }
+        nodes[0].position = None;
+
+        Tree {
+            game: self.game,
+            nodes,
+        }
+    }
+}
+
+impl Mcts {
+    pub fn new(budget: Budget, seed: u64) -> Self {
+        Mcts {
+            budget,
+            exploration: 2.0_f64.sqrt(),
+            rng: generate::seeded_rng(seed),
+            tree: None,
+        }
+    }
+
+    /// Explores the game within the budget. The statistics of earlier searches are
+    /// reused if the game continues from the position searched last time.
+    pub fn search(&mut self, game: &Game) -> SearchResult {
+        let mut tree = match self.tree.take().and_then(|tree| tree.advance(game)) {
+            Some(tree) => tree,
+            None => Tree::new(game),
+        };
+
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(duration) => start.elapsed() >= duration,
+            };
+            if done {
+                break;
+            }
+            self.iterate(&mut tree);
+            iterations += 1;
+        }
+
+        let mut moves: Vec<MoveStatistics> = tree.nodes[0]
+            .children
+            .iter()
+            .map(|&child| tree.nodes[child].statistics())
+            .collect();
+        moves.sort_by(|a, b| b.visits.cmp(&a.visits));
+        let result = SearchResult {
+            best: moves.first().map(|stats| stats.position),
+            moves,
+            playouts: tree.nodes[0].visits,
+        };
+
+        self.tree = Some(tree);
+        result
+    }
+
+    /// A single round of selection, expansion, playout and backpropagation.
+    fn iterate(&mut self, tree: &mut Tree) {
+        let mut game = tree.game.clone();
+
+        // Selection
+        let mut node = 0;
+        while tree.nodes[node].untried.is_empty() && !tree.nodes[node].children.is_empty() {
+            node = self.select_child(tree, node);
+            let position = tree.nodes[node].position.unwrap();
+            game.play(position)
+                .expect("The tree only contains legal moves.");
+        }
+
+        // Expansion
+        if !tree.nodes[node].untried.is_empty() {
+            let i = self.rng.gen_range(0, tree.nodes[node].untried.len());
+            let position = tree.nodes[node].untried.swap_remove(i);
+            game.play(position)
+                .expect("The tree only contains legal moves.");
+
+            let child = tree.nodes.len();
+            tree.nodes
+                .push(Node::new(Some(position), Some(node), &game));
+            tree.nodes[node].children.push(child);
+            node = child;
+        }
+
+        // Playout
+        let outcome = self.playout(&game);
+
+        // Backpropagation
+        let mut current = Some(node);
+        while let Some(i) = current {
+            let node = &mut tree.nodes[i];
+            node.visits += 1;
+            node.score += reward(outcome, node.player);
+            current = node.parent;
+        }
+    }
+
+    fn select_child(&self, tree: &Tree, node: usize) -> usize {
+        let log_visits = (tree.nodes[node].visits as f64).ln();
+        let uct = |child: usize| {
+            let child = &tree.nodes[child];
+            let visits = child.visits as f64;
+            child.score / visits + self.exploration * (log_visits / visits).sqrt()
+        };
+
+        let children = &tree.nodes[node].children;
+        let mut best = children[0];
+        for &child in &children[1..] {
+            if uct(child) > uct(best) {
+                best = child;
+            }
+        }
+        best
+    }
+
+    /// Plays random moves until the game is decided, using the faster bitboard.
+    fn playout(&mut self, game: &Game) -> board::Ownership {
+        BitBoard::from(game).playout(&mut self.rng)
+    }
+}
+
+fn reward(outcome: board::Ownership, player: board::Player) -> f64 {
+    let token: board::Token = player.into();
+    let owner: board::Ownership = token.into();
+    if outcome == owner {
+        1.0
+    } else if outcome == board::Ownership::Draw {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+impl Bot for Mcts {
+    fn choose_move(&mut self, game: &Game) -> Option<coord::Global> {
+        self.search(game).best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::*;
+
+    #[quickcheck]
+    fn chooses_legal_moves(game: Game) -> bool {
+        match Mcts::new(Budget::Iterations(50), 1).choose_move(&game) {
+            Some(position) => game.check(position).is_ok(),
+            None => game.outcome() != board::Ownership::Undecided,
+        }
+    }
+
+    #[test]
+    fn takes_winning_move() {
+        let game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                          O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        let result = Mcts::new(Budget::Iterations(500), 7).search(&game);
+        assert_eq!(result.best, Some("3/3".parse().unwrap()));
+        assert_eq!(result.moves.len(), 7);
+        assert_eq!(result.playouts, 500);
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let game = Game::new();
+        let first = Mcts::new(Budget::Iterations(300), 42).search(&game);
+        let second = Mcts::new(Budget::Iterations(300), 42).search(&game);
+        assert_eq!(first.moves, second.moves);
+    }
+
+    #[test]
+    fn reuses_subtree() {
+        let mut game = Game::new();
+        let mut mcts = Mcts::new(Budget::Iterations(1000), 3);
+        let first = mcts.search(&game);
+
+        // Play the most explored line, which keeps the most statistics.
+        let position = first.best.unwrap();
+        game.play(position).unwrap();
+        let second = mcts.search(&game);
+        assert!(second.playouts > 1000);
+        assert_eq!(second.playouts - 1000, first.moves[0].visits);
+
+        // A different game can't reuse anything.
+        let other = mcts.search(&Game::new());
+        assert_eq!(other.playouts, 1000);
+    }
+}
@@ -1,5 +1,6 @@
-use coord;
 use ggez::graphics::{Point2, Rect, Vector2};
+use tictactoe::coord;
+use tictactoe::sized::{Position, Square};
 
 #[derive(Debug, Clone)]
 pub struct Measure {
@@ -7,26 +8,37 @@ pub struct Measure {
     pub outer: HashtagMeasure,
 }
 
+/// The layout of a grid with `blocks` × `blocks` squares, separated by lines.
 #[derive(Debug, Clone)]
 pub struct HashtagMeasure {
+    pub blocks: u8,
     pub block_size: f32,
     pub line_width: f32,
     pub total_size: f32,
     pub inner_padding: f32,
 }
 
+/// The layout of boards nested several levels deep, from the outermost board inwards.
+#[derive(Debug, Clone)]
+pub struct NestedMeasure {
+    pub levels: Vec<HashtagMeasure>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MousePosition {
-    Local(coord::Global),
-    Region(coord::Local),
+    Local(Position),
+    Region(Square),
+    /// A cell of the innermost boards of a `NestedMeasure`.
+    Cell(coord::Path),
     BigHashtag,
     Outside,
 }
 
 impl HashtagMeasure {
-    pub fn new(block_size: f32, line_width: f32, inner_padding: f32) -> Self {
-        let total_size = 3.0 * block_size + 2.0 * line_width;
+    pub fn new(blocks: u8, block_size: f32, line_width: f32, inner_padding: f32) -> Self {
+        let total_size = blocks as f32 * block_size + (blocks - 1) as f32 * line_width;
         HashtagMeasure {
+            blocks,
             block_size,
             line_width,
             total_size,
@@ -34,12 +46,13 @@ impl HashtagMeasure {
         }
     }
 
-    pub fn from_size(total_size: f32, line_proportion: f32) -> Self {
-        assert!(line_proportion < 1.0 / 8.0);
+    pub fn from_size(blocks: u8, total_size: f32, line_proportion: f32) -> Self {
+        assert!(line_proportion < 1.0 / (4.0 * (blocks - 1) as f32));
         let line_width = line_proportion * total_size;
         let inner_padding = line_width;
-        let block_size = (total_size - 2.0 * line_width) / 3.0;
+        let block_size = (total_size - (blocks - 1) as f32 * line_width) / blocks as f32;
         HashtagMeasure {
+            blocks,
             block_size,
             line_width,
             total_size,
@@ -47,21 +60,27 @@ impl HashtagMeasure {
         }
     }
 
-    pub fn get_cell_rect(&self, coord: coord::Local) -> Rect {
+    /// All squares of the grid, row by row.
+    pub fn squares(&self) -> impl Iterator<Item = Square> {
+        let blocks = self.blocks;
+        (0..blocks * blocks).map(move |i| Square::new(i % blocks, i / blocks))
+    }
+
+    pub fn get_cell_rect(&self, coord: Square) -> Rect {
         let step_size = self.block_size + self.line_width;
         Rect::new(
-            coord.get_x() as f32 * step_size,
-            coord.get_y() as f32 * step_size,
+            coord.x as f32 * step_size,
+            coord.y as f32 * step_size,
             self.block_size,
             self.block_size,
         )
     }
 
-    pub fn get_offset(&self, coord: coord::Local) -> Vector2 {
+    pub fn get_offset(&self, coord: Square) -> Vector2 {
         Vector2::new(self.get_cell_rect(coord).x, self.get_cell_rect(coord).y)
     }
 
-    pub fn get_offset_with_padding(&self, coord: coord::Local) -> Vector2 {
+    pub fn get_offset_with_padding(&self, coord: Square) -> Vector2 {
         self.get_offset(coord) + Vector2::new(self.inner_padding, self.inner_padding)
     }
 
@@ -73,25 +92,33 @@ impl HashtagMeasure {
 impl Measure {
     #[allow(dead_code)] // Used by the tests
     fn from_inner_measures(
+        blocks: u8,
         small_block: f32,
         small_line: f32,
         small_padding: f32,
         big_line: f32,
         big_padding: f32,
     ) -> Self {
-        let inner = HashtagMeasure::new(small_block, small_line, small_padding);
-        let outer =
-            HashtagMeasure::new(inner.total_size + 2.0 * big_padding, big_line, big_padding);
+        let inner = HashtagMeasure::new(blocks, small_block, small_line, small_padding);
+        let outer = HashtagMeasure::new(
+            blocks,
+            inner.total_size + 2.0 * big_padding,
+            big_line,
+            big_padding,
+        );
         Measure { inner, outer }
     }
 
+    /// Regions and cells both have `blocks` squares per side.
     pub fn from_size(
+        blocks: u8,
         total_size: f32,
         outer_line_proportion: f32,
         inner_line_proportion: f32,
     ) -> Self {
-        let outer = HashtagMeasure::from_size(total_size, outer_line_proportion);
+        let outer = HashtagMeasure::from_size(blocks, total_size, outer_line_proportion);
         let inner = HashtagMeasure::from_size(
+            blocks,
             outer.get_block_size_without_padding(),
             inner_line_proportion,
         );
@@ -105,14 +132,14 @@ impl Measure {
         }
 
         // Search for the mouse position
-        for region in coord::Local::iter() {
+        for region in self.outer.squares() {
             if self.outer.get_cell_rect(region).contains(pos) {
                 // Region found, find local
-                for local in coord::Local::iter() {
+                for local in self.inner.squares() {
                     let mut rect = self.inner.get_cell_rect(local);
                     rect.translate(self.outer.get_offset_with_padding(region));
                     if rect.contains(pos) {
-                        return MousePosition::Local(coord::Global::new(region, local));
+                        return MousePosition::Local(Position::new(region, local));
                     }
                 }
                 // No local found, return region.
@@ -125,6 +152,53 @@ impl Measure {
     }
 }
 
+impl NestedMeasure {
+    /// Each level fills the space inside the padding of a square on the level above.
+    pub fn from_size(total_size: f32, line_proportions: &[f32]) -> Self {
+        let mut levels: Vec<HashtagMeasure> = vec![];
+        for &proportion in line_proportions {
+            let size = levels
+                .last()
+                .map_or(total_size, HashtagMeasure::get_block_size_without_padding);
+            levels.push(HashtagMeasure::from_size(3, size, proportion));
+        }
+        NestedMeasure { levels }
+    }
+
+    /// The offset of a board or cell, given by the squares leading to it.
+    pub fn get_offset(&self, squares: &[coord::Local]) -> Vector2 {
+        let mut offset = Vector2::new(0.0, 0.0);
+        for (level, &square) in self.levels.iter().zip(squares) {
+            offset = offset + level.get_offset_with_padding(square.into());
+        }
+        offset
+    }
+
+    pub fn resolve_mouse_position(&self, pos: Point2) -> MousePosition {
+        let outer = &self.levels[0];
+        if !Rect::new(0.0, 0.0, outer.total_size, outer.total_size).contains(pos) {
+            return MousePosition::Outside;
+        }
+
+        // Descend into the square containing the point on each level.
+        let mut squares = vec![];
+        let mut pos = pos;
+        for level in &self.levels {
+            match level
+                .squares()
+                .find(|&square| level.get_cell_rect(square).contains(pos))
+            {
+                Some(square) => {
+                    pos = pos - level.get_offset_with_padding(square);
+                    squares.push(coord::Local::new(square.x, square.y));
+                }
+                None => return MousePosition::BigHashtag,
+            }
+        }
+        MousePosition::Cell(coord::Path::new(squares))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use measure::*;
@@ -133,6 +207,7 @@ mod tests {
     impl Arbitrary for Measure {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             Measure::from_inner_measures(
+                g.gen(): u8 % 4 + 2,
                 g.gen(): f32 * 100.0 + 1.0,
                 g.gen(): f32 * 100.0,
                 g.gen(): f32 * 100.0,
@@ -145,6 +220,7 @@ mod tests {
     impl Arbitrary for HashtagMeasure {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             HashtagMeasure::new(
+                g.gen(): u8 % 4 + 2,
                 g.gen(): f32 * 100.0 + 1.0,
                 g.gen(): f32 * 100.0,
                 g.gen(): f32 * 100.0,
@@ -158,4 +234,17 @@ mod tests {
         let error = measure.outer.get_block_size_without_padding() - measure.inner.total_size;
         error.abs() < 0.1
     }
+
+    #[test]
+    fn resolve_nested_cells() {
+        let measure = NestedMeasure::from_size(700.0, &[0.02, 0.03, 0.04]);
+        let center = 0.5 * measure.levels[2].block_size;
+        for path in coord::Path::iter(3) {
+            let pos = Point2::new(center, center) + measure.get_offset(path.squares());
+            assert_eq!(
+                measure.resolve_mouse_position(pos),
+                MousePosition::Cell(path)
+            );
+        }
+    }
 }
@@ -0,0 +1,427 @@
+//! A single line text notation for positions, similar to FEN in chess.
+//!
+//! A board is written as nine regions separated by `/`, each region as nine cells.
+//! Both regions and cells are listed row by row, starting in the top left corner.
+//! Cells are `X`, `O` or `.` for a clear cell.
+//!
+//! When tokens may be placed into decided regions, a region can hold lines of both
+//! players. If the owner isn't the one evaluating the cells gives, the region ends with
+//! `=` and its owner, like `OOO...XXX=X`.
+//!
+//! A game adds the player to move (`X` or `O`) and the region they are sent to,
+//! numbered 1 to 9 in the same order, or `-` for a free move:
+//!
+//! ```text
+//! X......../........./........./........./........./........./........./........./......... O 1
+//! ```
+//!
+//! Moves are written as region and cell in the same numbering, like `1/1` for the
+//! move that lead to the position above.
+
+use board;
+use coord;
+use game;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything that can be wrong with a position string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsePositionError {
+    WrongRegionCount(usize),
+    WrongCellCount { region: usize, count: usize },
+    InvalidToken(char),
+    InvalidOwner { region: usize, owner: String },
+    MissingPlayer,
+    InvalidPlayer(String),
+    MissingRegion,
+    InvalidRegion(String),
+    UnplayableRegion(usize),
+    TrailingInput(String),
+}
+
+impl fmt::Display for ParsePositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsePositionError::WrongRegionCount(count) => {
+                write!(f, "expected 9 regions separated by '/', found {}", count)
+            }
+            ParsePositionError::WrongCellCount { region, count } => write!(
+                f,
+                "expected 9 cells in region {}, found {}",
+                region + 1,
+                count
+            ),
+            ParsePositionError::InvalidToken(c) => {
+                write!(f, "invalid cell '{}', expected 'X', 'O' or '.'", c)
+            }
+            ParsePositionError::InvalidOwner { region, owner } => write!(
+                f,
+                "invalid owner '{}' of region {}, expected 'X' or 'O' with a line there",
+                owner,
+                region + 1
+            ),
+            ParsePositionError::MissingPlayer => write!(f, "the player to move is missing"),
+            ParsePositionError::InvalidPlayer(s) => {
+                write!(f, "invalid player '{}', expected 'X' or 'O'", s)
+            }
+            ParsePositionError::MissingRegion => write!(f, "the active region is missing"),
+            ParsePositionError::InvalidRegion(s) => {
+                write!(f, "invalid region '{}', expected 1 to 9 or '-'", s)
+            }
+            ParsePositionError::UnplayableRegion(region) => {
+                write!(
+                    f,
+                    "region {} is already decided and can't be active",
+                    region + 1
+                )
+            }
+            ParsePositionError::TrailingInput(s) => write!(f, "unexpected input '{}'", s),
+        }
+    }
+}
+
+impl Error for ParsePositionError {}
+
+/// A move which is not of the form `region/cell`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseMoveError(pub String);
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid move '{}', expected region and cell from 1 to 9 like '5/3'",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseMoveError {}
+
+fn token_char(token: board::Token) -> char {
+    match token {
+        board::Token::Clear => '.',
+        board::Token::Circle => 'O',
+        board::Token::Cross => 'X',
+    }
+}
+
+fn parse_token(c: char) -> Result<board::Token, ParsePositionError> {
+    match c {
+        '.' => Ok(board::Token::Clear),
+        'O' => Ok(board::Token::Circle),
+        'X' => Ok(board::Token::Cross),
+        _ => Err(ParsePositionError::InvalidToken(c)),
+    }
+}
+
+/// The owner of the region with the given cells, who needs a line there.
+fn parse_owner(
+    region: usize,
+    cells: &[board::Token],
+    owner: &str,
+) -> Result<board::Ownership, ParsePositionError> {
+    let mut local = board::Local::new();
+    for (cell, &token) in coord::Local::iter().zip(cells) {
+        local[cell] = token;
+    }
+    match owner {
+        "O" if local.has_line(board::Token::Circle) => Ok(board::Ownership::Circle),
+        "X" if local.has_line(board::Token::Cross) => Ok(board::Ownership::Cross),
+        _ => Err(ParsePositionError::InvalidOwner {
+            region,
+            owner: owner.to_string(),
+        }),
+    }
+}
+
+fn player_char(player: board::Player) -> char {
+    token_char(player.into())
+}
+
+fn parse_player(s: &str) -> Result<board::Player, ParsePositionError> {
+    match s {
+        "O" => Ok(board::Player::Circle),
+        "X" => Ok(board::Player::Cross),
+        _ => Err(ParsePositionError::InvalidPlayer(s.to_string())),
+    }
+}
+
+fn parse_region(s: &str) -> Result<Option<coord::Local>, ParsePositionError> {
+    if s == "-" {
+        return Ok(None);
+    }
+    parse_digit(s)
+        .map(Some)
+        .ok_or_else(|| ParsePositionError::InvalidRegion(s.to_string()))
+}
+
+fn parse_digit(s: &str) -> Option<coord::Local> {
+    match s.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= 9 => Some(coord::Local::from_index(n - 1)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for coord::Global {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.get_region().index() + 1,
+            self.get_local().index() + 1
+        )
+    }
+}
+
+impl FromStr for coord::Global {
+    type Err = ParseMoveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let region = parts.next().and_then(parse_digit);
+        let local = parts.next().and_then(parse_digit);
+        match (region, local) {
+            (Some(region), Some(local)) => Ok(coord::Global::new(region, local)),
+            _ => Err(ParseMoveError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for board::Global {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for region in coord::Local::iter() {
+            if region.index() > 0 {
+                write!(f, "/")?;
+            }
+            let local = &self[region];
+            for cell in coord::Local::iter() {
+                write!(f, "{}", token_char(local[cell]))?;
+            }
+            let evaluated = if self.rules().inverts_captures() {
+                local.evaluate().inverted()
+            } else {
+                local.evaluate()
+            };
+            if local.total != evaluated {
+                write!(f, "={}", token_char(local.total.into()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for board::Global {
+    type Err = ParsePositionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regions: Vec<&str> = s.split('/').collect();
+        if regions.len() != 9 {
+            return Err(ParsePositionError::WrongRegionCount(regions.len()));
+        }
+
+        let mut entries = [board::Token::Clear; 81];
+        let mut owners = [None; 9];
+        for (i, region) in regions.iter().enumerate() {
+            let mut parts = region.splitn(2, '=');
+            let cells = parts.next().unwrap_or("");
+            let count = cells.chars().count();
+            if count != 9 {
+                return Err(ParsePositionError::WrongCellCount { region: i, count });
+            }
+            for (j, c) in cells.chars().enumerate() {
+                entries[9 * i + j] = parse_token(c)?;
+            }
+            if let Some(owner) = parts.next() {
+                owners[i] = Some(parse_owner(i, &entries[9 * i..9 * (i + 1)], owner)?);
+            }
+        }
+
+        Ok(board::Global::from_tokens_and_owners(&entries, &owners))
+    }
+}
+
+impl fmt::Display for game::Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ",
+            self.board(),
+            player_char(self.current_player())
+        )?;
+        match self.active_region() {
+            Some(region) => write!(f, "{}", region.index() + 1),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for game::Game {
+    type Err = ParsePositionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let board: board::Global = fields
+            .next()
+            .ok_or(ParsePositionError::WrongRegionCount(0))?
+            .parse()?;
+        let player = parse_player(fields.next().ok_or(ParsePositionError::MissingPlayer)?)?;
+        let region = parse_region(fields.next().ok_or(ParsePositionError::MissingRegion)?)?;
+        if let Some(rest) = fields.next() {
+            return Err(ParsePositionError::TrailingInput(rest.to_string()));
+        }
+
+        if let Some(region) = region {
+            if board[region].total != board::Ownership::Undecided {
+                return Err(ParsePositionError::UnplayableRegion(region.index()));
+            }
+        }
+
+        Ok(game::Game::from_position(board, player, region))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board;
+    use coord;
+    use game::Game;
+    use notation::*;
+    use rules::{FreeMove, Rules};
+
+    #[quickcheck]
+    fn move_round_trip(position: coord::Global) -> bool {
+        position.to_string().parse() == Ok(position)
+    }
+
+    #[test]
+    fn parse_moves() {
+        let center = coord::Local::new(1, 1);
+        assert_eq!("5/5".parse(), Ok(coord::Global::new(center, center)));
+        assert_eq!(
+            "1/9".parse(),
+            Ok(coord::Global::new(
+                coord::Local::new(0, 0),
+                coord::Local::new(2, 2)
+            ))
+        );
+        for invalid in ["", "5", "5/", "0/1", "1/10", "5/5/5", "a/b"].iter() {
+            assert_eq!(
+                invalid.parse::<coord::Global>(),
+                Err(ParseMoveError(invalid.to_string()))
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn board_round_trip(game: Game) -> bool {
+        let text = game.board().to_string();
+        let board: board::Global = text.parse().unwrap();
+        board.to_string() == text && board.total() == game.board().total()
+    }
+
+    #[quickcheck]
+    fn game_round_trip(game: Game) -> bool {
+        let text = game.to_string();
+        let parsed: Game = text.parse().unwrap();
+        parsed.to_string() == text
+            && parsed.legal_moves().collect::<Vec<_>>() == game.legal_moves().collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn parse_start_position() {
+        let empty = "........./........./........./........./........./\
+                     ........./........./........./......... X -";
+        assert_eq!(
+            empty.parse::<Game>().unwrap().to_string(),
+            Game::new().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let empty = "........./........./........./........./........./\
+                     ........./........./........./.........";
+        assert_eq!(
+            "........./........".parse::<board::Global>().unwrap_err(),
+            ParsePositionError::WrongRegionCount(2)
+        );
+        assert_eq!(
+            format!("{}. X -", empty).parse::<Game>().unwrap_err(),
+            ParsePositionError::WrongCellCount {
+                region: 8,
+                count: 10
+            }
+        );
+        assert_eq!(
+            empty
+                .replacen(".", "x", 1)
+                .parse::<board::Global>()
+                .unwrap_err(),
+            ParsePositionError::InvalidToken('x')
+        );
+        assert_eq!(
+            empty.parse::<Game>().unwrap_err(),
+            ParsePositionError::MissingPlayer
+        );
+        assert_eq!(
+            format!("{} X 0", empty).parse::<Game>().unwrap_err(),
+            ParsePositionError::InvalidRegion("0".to_string())
+        );
+        assert_eq!(
+            format!("{} X 1 X", empty).parse::<Game>().unwrap_err(),
+            ParsePositionError::TrailingInput("X".to_string())
+        );
+
+        let captured = empty.replacen("...", "XXX", 1);
+        assert_eq!(
+            format!("{} O 1", captured).parse::<Game>().unwrap_err(),
+            ParsePositionError::UnplayableRegion(0)
+        );
+        assert_eq!(
+            format!("{}=O", captured)
+                .parse::<board::Global>()
+                .unwrap_err(),
+            ParsePositionError::InvalidOwner {
+                region: 8,
+                owner: "O".to_string()
+            }
+        );
+    }
+
+    /// Cross captures the first region with its bottom row before circle completes the
+    /// top row, which comes first when the cells are evaluated.
+    #[test]
+    fn owners_which_the_cells_dont_tell() {
+        let mut board = board::Global::with_rules(Rules {
+            free_move: FreeMove::AnyRegion,
+            ..Rules::default()
+        });
+        let region = coord::Local::new(0, 0);
+        for &(cell, token) in &[
+            (6, board::Token::Cross),
+            (7, board::Token::Cross),
+            (8, board::Token::Cross),
+            (0, board::Token::Circle),
+            (1, board::Token::Circle),
+            (2, board::Token::Circle),
+        ] {
+            board.place_token(
+                coord::Global::new(region, coord::Local::from_index(cell)),
+                token,
+            );
+        }
+        assert_eq!(board[region].total, board::Ownership::Cross);
+
+        let text = board.to_string();
+        assert!(text.starts_with("OOO...XXX=X/"));
+        let parsed: board::Global = text.parse().unwrap();
+        assert_eq!(parsed[region].total, board::Ownership::Cross);
+        assert_eq!(parsed.hash(), board.hash());
+
+        let evaluated: board::Global = text.replacen("=X", "", 1).parse().unwrap();
+        assert_eq!(evaluated[region].total, board::Ownership::Circle);
+        assert_ne!(evaluated.hash(), board.hash());
+    }
+}
@@ -0,0 +1,100 @@
+//! Perft counts the move sequences of a given length from a position. Comparing these
+//! counts with known values, or between `game::Game` and `bitboard::BitBoard`, finds
+//! bugs in the rules which would otherwise only show up as odd games.
+
+use bitboard::BitBoard;
+use coord;
+use game::Game;
+
+/// Counts the positions reached after exactly `depth` moves. Decided games end a
+/// sequence early and aren't counted unless the depth is reached.
+pub fn perft(game: &mut Game, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves: Vec<coord::Global> = game.legal_moves().collect();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut count = 0;
+    for position in moves {
+        game.play(position).expect("Generated moves are legal.");
+        count += perft(game, depth - 1);
+        game.undo();
+    }
+    count
+}
+
+/// Splits the perft count by the first move, which narrows down where two counts differ.
+pub fn divide(game: &mut Game, depth: u32) -> Vec<(coord::Global, u64)> {
+    let moves: Vec<coord::Global> = game.legal_moves().collect();
+    moves
+        .into_iter()
+        .map(|position| {
+            game.play(position).expect("Generated moves are legal.");
+            let count = perft(game, depth.saturating_sub(1));
+            game.undo();
+            (position, count)
+        })
+        .collect()
+}
+
+/// The same count as `perft`, but much faster.
+pub fn perft_bitboard(board: &mut BitBoard, depth: u32) -> u64 {
+    let mut moves = vec![];
+    board.legal_moves(&mut moves);
+    if depth <= 1 {
+        return if depth == 0 { 1 } else { moves.len() as u64 };
+    }
+
+    let mut count = 0;
+    for position in moves {
+        let undo = board.make_move(position);
+        count += perft_bitboard(board, depth - 1);
+        board.unmake_move(position, undo);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use perft::*;
+
+    /// Perft counts from the empty board, starting with depth 0.
+    const EMPTY_BOARD: [u64; 7] = [1, 81, 720, 6336, 55080, 473_256, 4_020_960];
+
+    #[test]
+    fn empty_board_counts() {
+        for (depth, &expected) in EMPTY_BOARD.iter().enumerate().take(5) {
+            assert_eq!(perft(&mut Game::new(), depth as u32), expected);
+        }
+    }
+
+    #[test]
+    fn empty_board_counts_on_bitboard() {
+        for (depth, &expected) in EMPTY_BOARD.iter().enumerate() {
+            assert_eq!(perft_bitboard(&mut BitBoard::new(), depth as u32), expected);
+        }
+    }
+
+    #[quickcheck]
+    fn game_and_bitboard_agree(game: Game) -> bool {
+        let mut board = BitBoard::from(&game);
+        perft(&mut game.clone(), 3) == perft_bitboard(&mut board, 3)
+    }
+
+    #[test]
+    fn divide_adds_up() {
+        let mut game: Game = "XXX....../XXX....../XX......./OO......./OO......./\
+                              O......../........./........./......... X 3"
+            .parse()
+            .unwrap();
+        let split = divide(&mut game, 3);
+        assert_eq!(split.len(), 7);
+        // Completing the third region wins, so nothing follows that move.
+        assert!(split.contains(&("3/3".parse().unwrap(), 0)));
+        let total: u64 = split.iter().map(|&(_, count)| count).sum();
+        assert_eq!(total, perft(&mut game, 3));
+    }
+}
@@ -0,0 +1,291 @@
+//! The interface between the window and the game shown in it, so the same drawing and
+//! input code works for the standard game and for other board sizes.
+
+use tictactoe::board::{Ownership, Player, Token};
+use tictactoe::coord;
+use tictactoe::game::{self, IllegalMove};
+use tictactoe::rules::Rules;
+use tictactoe::sized::{self, Position, Size, Square};
+
+/// The regions the opponent may play in after a move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NextRegions {
+    /// Only the region the opponent is sent to, or all regions with space left.
+    pub regions: Vec<Square>,
+    /// Whether the opponent may choose any region.
+    pub free_move: bool,
+}
+
+pub trait Playable {
+    fn size(&self) -> Size;
+    fn current_player(&self) -> Player;
+    fn outcome(&self) -> Ownership;
+    fn owner(&self, region: Square) -> Ownership;
+    /// Whether neither player can complete a line in the region anymore.
+    fn is_dead(&self, region: Square) -> bool;
+    fn token(&self, position: Position) -> Token;
+    fn can_place_in_region(&self, region: Square) -> bool;
+    fn check(&self, position: Position) -> Result<(), IllegalMove>;
+    fn play(&mut self, position: Position) -> Result<(), IllegalMove>;
+    /// Returns whether there was a move to take back.
+    fn undo(&mut self) -> bool;
+    /// Returns whether there was a move to replay.
+    fn redo(&mut self) -> bool;
+    /// The regions of the winning line on the meta-board, from one end to the other.
+    fn winning_line(&self) -> Option<Vec<Square>>;
+    /// The cells of the line which captured the region, from one end to the other.
+    fn region_winning_line(&self, region: Square) -> Option<Vec<Square>>;
+    /// The most recent move, if the game was played from the start.
+    fn last_move(&self) -> Option<Position>;
+    /// Where the opponent may play after the move, `None` if it is illegal or ends the game.
+    fn next_regions(&self, position: Position) -> Option<NextRegions>;
+
+    /// The standard game, which the computer opponents and save files need.
+    fn standard(&self) -> Option<&game::Game> {
+        None
+    }
+}
+
+fn local(square: Square) -> coord::Local {
+    coord::Local::new(square.x, square.y)
+}
+
+fn global(position: Position) -> coord::Global {
+    coord::Global::new(local(position.region), local(position.cell))
+}
+
+impl Playable for game::Game {
+    fn size(&self) -> Size {
+        Size::default()
+    }
+
+    fn current_player(&self) -> Player {
+        game::Game::current_player(self)
+    }
+
+    fn outcome(&self) -> Ownership {
+        game::Game::outcome(self)
+    }
+
+    fn owner(&self, region: Square) -> Ownership {
+        self.board()[local(region)].total
+    }
+
+    fn is_dead(&self, region: Square) -> bool {
+        self.board()[local(region)].is_dead()
+    }
+
+    fn token(&self, position: Position) -> Token {
+        self.board()[local(position.region)][local(position.cell)]
+    }
+
+    fn can_place_in_region(&self, region: Square) -> bool {
+        game::Game::can_place_in_region(self, local(region))
+    }
+
+    fn check(&self, position: Position) -> Result<(), IllegalMove> {
+        game::Game::check(self, global(position))
+    }
+
+    fn play(&mut self, position: Position) -> Result<(), IllegalMove> {
+        game::Game::play(self, global(position))
+    }
+
+    fn undo(&mut self) -> bool {
+        game::Game::undo(self).is_some()
+    }
+
+    fn redo(&mut self) -> bool {
+        game::Game::redo(self).is_some()
+    }
+
+    fn winning_line(&self) -> Option<Vec<Square>> {
+        self.board()
+            .winning_line()
+            .map(|line| line.iter().map(|&region| region.into()).collect())
+    }
+
+    fn region_winning_line(&self, region: Square) -> Option<Vec<Square>> {
+        self.board()
+            .region_winning_line(local(region))
+            .map(|line| line.iter().map(|&cell| cell.into()).collect())
+    }
+
+    fn last_move(&self) -> Option<Position> {
+        self.moves().last().map(Position::from)
+    }
+
+    fn next_regions(&self, position: Position) -> Option<NextRegions> {
+        let mut next = self.clone();
+        if game::Game::play(&mut next, global(position)).is_err()
+            || game::Game::outcome(&next) != Ownership::Undecided
+        {
+            return None;
+        }
+        Some(NextRegions {
+            regions: coord::Local::iter()
+                .filter(|&region| game::Game::can_place_in_region(&next, region))
+                .map(Square::from)
+                .collect(),
+            free_move: next.active_region().is_none(),
+        })
+    }
+
+    fn standard(&self) -> Option<&game::Game> {
+        Some(self)
+    }
+}
+
+impl Playable for sized::Game {
+    fn size(&self) -> Size {
+        sized::Game::size(self)
+    }
+
+    fn current_player(&self) -> Player {
+        sized::Game::current_player(self)
+    }
+
+    fn outcome(&self) -> Ownership {
+        sized::Game::outcome(self)
+    }
+
+    fn owner(&self, region: Square) -> Ownership {
+        self.board().owner(region)
+    }
+
+    fn is_dead(&self, region: Square) -> bool {
+        self.board().is_dead(region)
+    }
+
+    fn token(&self, position: Position) -> Token {
+        self.board().token(position)
+    }
+
+    fn can_place_in_region(&self, region: Square) -> bool {
+        sized::Game::can_place_in_region(self, region)
+    }
+
+    fn check(&self, position: Position) -> Result<(), IllegalMove> {
+        sized::Game::check(self, position)
+    }
+
+    fn play(&mut self, position: Position) -> Result<(), IllegalMove> {
+        sized::Game::play(self, position)
+    }
+
+    fn undo(&mut self) -> bool {
+        sized::Game::undo(self).is_some()
+    }
+
+    fn redo(&mut self) -> bool {
+        sized::Game::redo(self).is_some()
+    }
+
+    fn winning_line(&self) -> Option<Vec<Square>> {
+        self.board().winning_line()
+    }
+
+    fn region_winning_line(&self, region: Square) -> Option<Vec<Square>> {
+        self.board().region_winning_line(region)
+    }
+
+    fn last_move(&self) -> Option<Position> {
+        self.moves().last()
+    }
+
+    fn next_regions(&self, position: Position) -> Option<NextRegions> {
+        let mut next = self.clone();
+        if sized::Game::play(&mut next, position).is_err()
+            || sized::Game::outcome(&next) != Ownership::Undecided
+        {
+            return None;
+        }
+        Some(NextRegions {
+            regions: next
+                .size()
+                .squares()
+                .filter(|&region| sized::Game::can_place_in_region(&next, region))
+                .collect(),
+            free_move: next.active_region().is_none(),
+        })
+    }
+}
+
+/// A new game of the given size. Only the standard size supports other rules, which is
+/// why the command line rejects rule variants together with another size.
+pub fn new_game(size: Size, rules: Rules) -> Box<dyn Playable> {
+    debug_assert!(size == Size::default() || rules == Rules::default());
+    if size == Size::default() {
+        Box::new(game::Game::with_rules(rules))
+    } else {
+        Box::new(sized::Game::new(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use playable::*;
+
+    fn at(rx: u8, ry: u8, x: u8, y: u8) -> Position {
+        Position::new(Square::new(rx, ry), Square::new(x, y))
+    }
+
+    #[test]
+    fn last_move_follows_undo() {
+        let mut game: Box<dyn Playable> = Box::new(game::Game::new());
+        assert_eq!(game.last_move(), None);
+        game.play(at(1, 1, 0, 2)).unwrap();
+        game.play(at(0, 2, 2, 2)).unwrap();
+        assert_eq!(game.last_move(), Some(at(0, 2, 2, 2)));
+        assert!(game.undo());
+        assert_eq!(game.last_move(), Some(at(1, 1, 0, 2)));
+    }
+
+    #[test]
+    fn next_regions_of_the_standard_game() {
+        let mut game = game::Game::new();
+        game.play(global(at(1, 1, 0, 2))).unwrap();
+        assert_eq!(
+            game.next_regions(at(0, 2, 2, 2)),
+            Some(NextRegions {
+                regions: vec![Square::new(2, 2)],
+                free_move: false,
+            })
+        );
+        // Cross has to play in region (0, 2).
+        assert_eq!(game.next_regions(at(1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn next_regions_of_free_and_final_moves() {
+        // Cross captures region (0, 0) and then wins with (0, 0) and (1, 0).
+        let mut game = sized::Game::new(Size::new(2, 2).unwrap());
+        game.play(at(0, 0, 0, 0)).unwrap();
+        assert_eq!(
+            Playable::next_regions(&game, at(0, 0, 1, 0)),
+            Some(NextRegions {
+                regions: vec![Square::new(1, 0)],
+                free_move: false,
+            })
+        );
+        for &position in &[
+            at(0, 0, 1, 0),
+            at(1, 0, 1, 0),
+            at(1, 0, 0, 0),
+            at(0, 0, 0, 1),
+        ] {
+            game.play(position).unwrap();
+        }
+        // Circle sends cross back to the captured region.
+        assert_eq!(
+            Playable::next_regions(&game, at(0, 1, 0, 0)),
+            Some(NextRegions {
+                regions: vec![Square::new(1, 0), Square::new(0, 1), Square::new(1, 1)],
+                free_move: true,
+            })
+        );
+        game.play(at(0, 1, 0, 0)).unwrap();
+        assert_eq!(Playable::next_regions(&game, at(1, 0, 0, 1)), None);
+        assert_eq!(Playable::last_move(&game), Some(at(0, 1, 0, 0)));
+    }
+}
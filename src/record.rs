@@ -0,0 +1,454 @@
+//! A human readable file format for complete games.
+//!
+//! A record starts with headers in square brackets, followed by the moves in the
+//! notation from `notation`. Move numbers are optional when reading.
+//!
+//! ```text
+//! [Cross "Alice"]
+//! [Circle "Bob"]
+//! [Date "2018-06-02"]
+//! [Result "*"]
+//!
+//! 1. 5/5 5/1
+//! 2. 1/9 9/5
+//! ```
+//!
+//! Games which don't start on an empty board have a `Position` header, and games
+//! played by other than the default rules have a `Rules` header. The result is
+//! `X` or `O` for the winner, `Draw`, or `*` for a game which is still running.
+//!
+//! Inside header values, a backslash escapes the next character, so names can contain
+//! `"`, `]` and `\` as `\"`, `\]` and `\\`. Values can't span several lines.
+
+use board;
+use coord;
+use game::{Game, IllegalMove};
+use notation::{ParseMoveError, ParsePositionError};
+use rules::{ParseRulesError, Rules};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A game together with some information about it.
+#[derive(Clone, Debug, Default)]
+pub struct Record {
+    pub cross: Option<String>,
+    pub circle: Option<String>,
+    pub date: Option<String>,
+    pub rules: Rules,
+    /// The starting position, if it is not the empty board.
+    pub start: Option<Game>,
+    pub moves: Vec<coord::Global>,
+}
+
+/// Everything that can be wrong with a record, either while reading or replaying it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    InvalidHeader(String),
+    UnknownHeader(String),
+    InvalidRules(ParseRulesError),
+    InvalidPosition(ParsePositionError),
+    InvalidMove(ParseMoveError),
+    IllegalMove {
+        number: usize,
+        position: coord::Global,
+        reason: IllegalMove,
+    },
+    WrongResult {
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::InvalidHeader(line) => write!(f, "invalid header '{}'", line),
+            RecordError::UnknownHeader(key) => write!(f, "unknown header '{}'", key),
+            RecordError::InvalidRules(e) => write!(f, "{}", e),
+            RecordError::InvalidPosition(e) => write!(f, "invalid starting position: {}", e),
+            RecordError::InvalidMove(e) => write!(f, "{}", e),
+            RecordError::IllegalMove {
+                number,
+                position,
+                reason,
+            } => write!(f, "move {} ({}) is illegal: {}", number, position, reason),
+            RecordError::WrongResult { expected, found } => write!(
+                f,
+                "the result header says '{}', but the moves lead to '{}'",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+impl From<ParseRulesError> for RecordError {
+    fn from(e: ParseRulesError) -> Self {
+        RecordError::InvalidRules(e)
+    }
+}
+
+impl From<ParsePositionError> for RecordError {
+    fn from(e: ParsePositionError) -> Self {
+        RecordError::InvalidPosition(e)
+    }
+}
+
+impl From<ParseMoveError> for RecordError {
+    fn from(e: ParseMoveError) -> Self {
+        RecordError::InvalidMove(e)
+    }
+}
+
+fn result_string(outcome: board::Ownership) -> &'static str {
+    match outcome {
+        board::Ownership::Undecided => "*",
+        board::Ownership::Cross => "X",
+        board::Ownership::Circle => "O",
+        board::Ownership::Draw => "Draw",
+    }
+}
+
+impl Record {
+    /// Records all moves of the game, starting from the position the game started in,
+    /// together with the names of both players and the date the game was played.
+    pub fn from_game(game: &Game, cross: &str, circle: &str, date: &str) -> Self {
+        let mut start = game.clone();
+        while start.undo().is_some() {}
+        let start = if start.to_string() == Game::new().to_string() {
+            None
+        } else {
+            Some(start)
+        };
+
+        Record {
+            cross: Some(cross.to_string()),
+            circle: Some(circle.to_string()),
+            date: Some(date.to_string()),
+            rules: game.rules(),
+            start,
+            moves: game.moves().collect(),
+        }
+    }
+
+    /// Plays all moves from the starting position, checking each of them against the rules.
+    pub fn replay(&self) -> Result<Game, RecordError> {
+        let mut game = match self.start {
+            Some(ref start) => {
+                let mut board = start.board().clone();
+                board.set_rules(self.rules);
+                Game::from_position(board, start.current_player(), start.active_region())
+            }
+            None => Game::with_rules(self.rules),
+        };
+        for (i, &position) in self.moves.iter().enumerate() {
+            game.play(position)
+                .map_err(|reason| RecordError::IllegalMove {
+                    number: i + 1,
+                    position,
+                    reason,
+                })?;
+        }
+        Ok(game)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref name) = self.cross {
+            writeln!(f, "[Cross \"{}\"]", escape(name))?;
+        }
+        if let Some(ref name) = self.circle {
+            writeln!(f, "[Circle \"{}\"]", escape(name))?;
+        }
+        if let Some(ref date) = self.date {
+            writeln!(f, "[Date \"{}\"]", escape(date))?;
+        }
+        if let Ok(game) = self.replay() {
+            writeln!(f, "[Result \"{}\"]", result_string(game.outcome()))?;
+        }
+        if self.rules != Rules::default() {
+            writeln!(f, "[Rules \"{}\"]", self.rules)?;
+        }
+        if let Some(ref start) = self.start {
+            writeln!(f, "[Position \"{}\"]", start)?;
+        }
+        writeln!(f)?;
+
+        for (i, pair) in self.moves.chunks(2).enumerate() {
+            write!(f, "{}.", i + 1)?;
+            for position in pair {
+                write!(f, " {}", position)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Record {
+    type Err = RecordError;
+
+    /// Reads a record and validates it by replaying all moves.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut record = Record::default();
+        let mut result = None;
+
+        let mut lines = s.lines().map(str::trim).peekable();
+        while let Some(line) = lines.peek().cloned() {
+            if line.is_empty() {
+                lines.next();
+                continue;
+            }
+            if !line.starts_with('[') {
+                break;
+            }
+            lines.next();
+
+            let (key, value) = parse_header(line)?;
+            match key {
+                "Cross" => record.cross = Some(value),
+                "Circle" => record.circle = Some(value),
+                "Date" => record.date = Some(value),
+                "Result" => result = Some(value),
+                "Rules" => record.rules = value.parse()?,
+                "Position" => record.start = Some(value.parse()?),
+                _ => return Err(RecordError::UnknownHeader(key.to_string())),
+            }
+        }
+
+        for word in lines.flat_map(str::split_whitespace) {
+            // Skip the move numbers
+            if word.ends_with('.') {
+                continue;
+            }
+            record.moves.push(word.parse()?);
+        }
+
+        let game = record.replay()?;
+        if let Some(found) = result {
+            let expected = result_string(game.outcome());
+            if found != expected {
+                return Err(RecordError::WrongResult {
+                    expected: expected.to_string(),
+                    found,
+                });
+            }
+        }
+
+        Ok(record)
+    }
+}
+
+/// Splits a line like `[Key "Value"]` into key and value.
+fn parse_header(line: &str) -> Result<(&str, String), RecordError> {
+    let invalid = || RecordError::InvalidHeader(line.to_string());
+    if !line.ends_with(']') {
+        return Err(invalid());
+    }
+    let inner = &line[1..line.len() - 1];
+    let split = inner.find(' ').ok_or_else(invalid)?;
+    let (key, value) = (&inner[..split], inner[split..].trim());
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(invalid());
+    }
+    let value = unescape(&value[1..value.len() - 1]).ok_or_else(invalid)?;
+    Ok((key, value))
+}
+
+/// Formats the UTC date which is `since_epoch` after the Unix epoch as `YYYY-MM-DD`, the
+/// format of the `Date` header.
+pub fn format_date(since_epoch: Duration) -> String {
+    // Converts days to a date in the proleptic Gregorian calendar, with years starting in
+    // March so that leap days come last.
+    let days = since_epoch.as_secs() / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Puts a backslash before every character which would end the header value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || c == '"' || c == ']' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Reverses `escape`, or returns `None` for an unescaped quote or a trailing backslash.
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            '"' => return None,
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::TestResult;
+    use record::*;
+
+    #[quickcheck]
+    fn record_round_trip(game: Game) -> bool {
+        let text = Record::from_game(&game, "Alice", "Bob", "2018-06-02").to_string();
+        let record: Record = text.parse().unwrap();
+        let replayed = record.replay().unwrap();
+        replayed.to_string() == game.to_string()
+            && replayed.moves().collect::<Vec<_>>() == game.moves().collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn read_record() {
+        let text = "[Cross \"Alice\"]\n\
+                    [Circle \"Bob\"]\n\
+                    [Result \"*\"]\n\
+                    \n\
+                    1. 5/5 5/1\n\
+                    2. 1/9 9/5\n";
+        let record: Record = text.parse().unwrap();
+        assert_eq!(record.cross, Some("Alice".to_string()));
+        assert_eq!(record.circle, Some("Bob".to_string()));
+        assert_eq!(record.moves.len(), 4);
+        assert_eq!(record.to_string(), text);
+    }
+
+    #[quickcheck]
+    fn headers_round_trip(cross: String, circle: String, date: String) -> TestResult {
+        if [&cross, &circle, &date]
+            .iter()
+            .any(|value| value.contains(|c| c == '\n' || c == '\r'))
+        {
+            return TestResult::discard();
+        }
+        let record = Record::from_game(&Game::new(), &cross, &circle, &date);
+        let parsed: Record = record.to_string().parse().unwrap();
+        TestResult::from_bool(
+            parsed.cross == Some(cross)
+                && parsed.circle == Some(circle)
+                && parsed.date == Some(date),
+        )
+    }
+
+    #[test]
+    fn escape_header_values() {
+        let record = Record::from_game(&Game::new(), "The \"Cross\"", "[O]\\", "today");
+        let text = record.to_string();
+        assert!(text.starts_with(
+            "[Cross \"The \\\"Cross\\\"\"]\n\
+             [Circle \"[O\\]\\\\\"]\n\
+             [Date \"today\"]\n"
+        ));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed.cross, Some("The \"Cross\"".to_string()));
+        assert_eq!(parsed.circle, Some("[O]\\".to_string()));
+        assert_eq!(
+            "[Cross \"A\"B\"]".parse::<Record>().unwrap_err(),
+            RecordError::InvalidHeader("[Cross \"A\"B\"]".to_string())
+        );
+    }
+
+    #[test]
+    fn format_dates() {
+        let day = |days: u64| format_date(Duration::from_secs(days * 86_400 + 3600));
+        assert_eq!(day(0), "1970-01-01");
+        assert_eq!(day(59), "1970-03-01");
+        assert_eq!(day(11_016), "2000-02-29");
+        assert_eq!(day(17_684), "2018-06-02");
+        assert_eq!(day(20_744), "2026-10-18");
+    }
+
+    #[test]
+    fn reject_invalid_records() {
+        assert_eq!(
+            "1. 5/5 1/1".parse::<Record>().unwrap_err(),
+            RecordError::IllegalMove {
+                number: 2,
+                position: "1/1".parse().unwrap(),
+                reason: IllegalMove::WrongRegion,
+            }
+        );
+        assert_eq!(
+            "[Result \"X\"]\n1. 5/5".parse::<Record>().unwrap_err(),
+            RecordError::WrongResult {
+                expected: "*".to_string(),
+                found: "X".to_string(),
+            }
+        );
+        assert_eq!(
+            "[Cross Alice]".parse::<Record>().unwrap_err(),
+            RecordError::InvalidHeader("[Cross Alice]".to_string())
+        );
+        assert_eq!(
+            "1. 5-5".parse::<Record>().unwrap_err(),
+            RecordError::InvalidMove(ParseMoveError("5-5".to_string()))
+        );
+    }
+
+    #[test]
+    fn keep_starting_position() {
+        let mut game: Game = "X......../........./........./........./........./\
+                              ........./........./........./......... O 1"
+            .parse()
+            .unwrap();
+        game.play("1/5".parse().unwrap()).unwrap();
+
+        let record = Record::from_game(&game, "Alice", "Bob", "2018-06-02");
+        assert!(record.start.is_some());
+        assert_eq!(record.moves, vec!["1/5".parse().unwrap()]);
+        let text = record.to_string();
+        assert_eq!(
+            text.parse::<Record>()
+                .unwrap()
+                .replay()
+                .unwrap()
+                .to_string(),
+            game.to_string()
+        );
+    }
+
+    #[test]
+    fn keep_rules() {
+        let rules: Rules = "draws-for-both,majority".parse().unwrap();
+        let mut game = Game::with_rules(rules);
+        game.play("5/5".parse().unwrap()).unwrap();
+
+        let text = Record::from_game(&game, "Alice", "Bob", "2018-06-02").to_string();
+        assert!(text.contains("[Rules \"draws-for-both,majority\"]\n"));
+        let replayed = text.parse::<Record>().unwrap().replay().unwrap();
+        assert_eq!(replayed.rules(), rules);
+
+        assert!(
+            !Record::from_game(&Game::new(), "Alice", "Bob", "2018-06-02")
+                .to_string()
+                .contains("Rules")
+        );
+        assert_eq!(
+            "[Rules \"gomoku\"]".parse::<Record>().unwrap_err(),
+            RecordError::InvalidRules(ParseRulesError("gomoku".to_string()))
+        );
+    }
+}
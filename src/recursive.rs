@@ -0,0 +1,345 @@
+//! "Ultimate Ultimate" tic-tac-toe: every cell of a tic-tac-toe board is itself a board,
+//! nested `depth` levels deep. With a depth of two this is the standard game, the mode
+//! played here has three levels and 729 cells.
+//!
+//! A move at the cell `[a, b, c]` sends the opponent to the innermost board `[b, c]`,
+//! just like a move in the cell `b` of a region sends the opponent to region `b` in the
+//! standard game. If that board is decided, the opponent may play anywhere in the
+//! enclosing board `[b]`, and if that one is decided too, anywhere at all. Winning a
+//! board counts as a token on the board enclosing it, all the way up to the outermost
+//! board, which decides the game. These games are played by the default rules.
+
+use board::{self, Ownership, Player, Token, LINES};
+use coord::{self, Path};
+use game::IllegalMove;
+use history::History;
+
+/// The number of levels in the recursive mode.
+pub const DEPTH: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct Board {
+    tokens: Vec<Token>,
+    /// The owners of all boards, by level. The outermost board is the only one on level
+    /// zero, the innermost boards, which hold the tokens, are on level `depth - 1`.
+    owners: Vec<Vec<Ownership>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: Board,
+    current_player: Player,
+    history: History<Path>,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(DEPTH)
+    }
+}
+
+impl Board {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth >= 1);
+        Board {
+            tokens: vec![Token::Clear; 9usize.pow(depth as u32)],
+            owners: (0..depth)
+                .map(|level| vec![Ownership::Undecided; 9usize.pow(level as u32)])
+                .collect(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.owners.len()
+    }
+
+    pub fn token(&self, path: &Path) -> Token {
+        assert_eq!(path.depth(), self.depth());
+        self.tokens[path.index()]
+    }
+
+    /// The owner of the board reached by the squares, so an empty slice gives the owner
+    /// of the outermost board. A full path gives the owner of a single cell.
+    pub fn owner(&self, board: &[coord::Local]) -> Ownership {
+        if board.len() == self.depth() {
+            self.tokens[coord::index(board)].into()
+        } else {
+            self.owners[board.len()][coord::index(board)]
+        }
+    }
+
+    /// Who owns the outermost board. Anything but `Ownership::Undecided` means the game is
+    /// over.
+    pub fn total(&self) -> Ownership {
+        self.owners[0][0]
+    }
+
+    /// The squares which won the board reached by the given squares, if it is won. An
+    /// empty slice gives the line which won the game, a full path gives `None`.
+    pub fn winning_line(&self, board: &[coord::Local]) -> Option<[coord::Local; 3]> {
+        if board.len() == self.depth() {
+            return None;
+        }
+        let owners = self.square_owners(board);
+        board::find_line(&LINES, self.owner(board), |i| owners[i]).map(board::to_locals)
+    }
+
+    /// The owners of the nine squares of the board reached by the given squares.
+    fn square_owners(&self, board: &[coord::Local]) -> Vec<Ownership> {
+        let mut squares = board.to_vec();
+        coord::Local::iter()
+            .map(|square| {
+                squares.push(square);
+                let owner = self.owner(&squares);
+                squares.pop();
+                owner
+            })
+            .collect()
+    }
+
+    /// Places a token into a clear cell and updates the owners of all boards containing it.
+    pub fn place_token(&mut self, path: &Path, token: Token) {
+        self.tokens[path.index()] = token;
+        self.update(path);
+    }
+
+    /// Reverts `place_token` for the most recently placed token.
+    pub fn remove_token(&mut self, path: &Path) {
+        self.tokens[path.index()] = Token::Clear;
+        self.update(path);
+    }
+
+    /// Evaluates the boards containing the cell again, from the innermost one outwards.
+    fn update(&mut self, path: &Path) {
+        let mut board = path.squares().to_vec();
+        while board.pop().is_some() {
+            let squares = self.square_owners(&board);
+            self.owners[board.len()][coord::index(&board)] =
+                board::evaluate_grid(&LINES, 9, |i| squares[i]);
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(DEPTH)
+    }
+}
+
+impl Game {
+    pub fn new(depth: usize) -> Self {
+        Game {
+            board: Board::new(depth),
+            current_player: Player::Cross,
+            history: History::new(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.board.depth()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn current_player(&self) -> Player {
+        self.current_player
+    }
+
+    pub fn outcome(&self) -> Ownership {
+        self.board.total()
+    }
+
+    /// The board the current player has to play in, as the squares leading to it. This is
+    /// the innermost board the last move sent them to, or the closest enclosing board
+    /// which is still undecided.
+    pub fn active_board(&self) -> Vec<coord::Local> {
+        let mut board = match self.history.last() {
+            Some(path) => path.squares()[1..].to_vec(),
+            None => vec![],
+        };
+        while !board.is_empty() && self.board.owner(&board) != Ownership::Undecided {
+            board.pop();
+        }
+        board
+    }
+
+    /// A board is playable if it is undecided and lies inside the active board, or
+    /// contains it.
+    pub fn can_place_in(&self, board: &[coord::Local]) -> bool {
+        let active = self.active_board();
+        let is_active = board.starts_with(&active) || active.starts_with(board);
+        is_active && self.board.owner(board) == Ownership::Undecided
+    }
+
+    /// Checks if the current player may place a token at the given cell.
+    pub fn check(&self, path: &Path) -> Result<(), IllegalMove> {
+        let squares = path.squares();
+        if self.outcome() != Ownership::Undecided {
+            Err(IllegalMove::GameOver)
+        } else if !squares.starts_with(&self.active_board())
+            || (1..squares.len()).any(|level| !self.can_place_in(&squares[..level]))
+        {
+            Err(IllegalMove::WrongRegion)
+        } else if self.board.token(path) != Token::Clear {
+            Err(IllegalMove::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// All cells the current player may place a token at, ordered by their index.
+    pub fn legal_moves<'a>(&'a self) -> impl Iterator<Item = Path> + 'a {
+        Path::iter(self.depth()).filter(move |path| self.check(path).is_ok())
+    }
+
+    /// All moves played so far, starting with the first one.
+    pub fn moves(&self) -> &[Path] {
+        self.history.played()
+    }
+
+    /// Places a token for the current player and discards all moves which could be redone.
+    pub fn play(&mut self, path: Path) -> Result<(), IllegalMove> {
+        self.check(&path)?;
+        self.place(&path);
+        self.history.play(path);
+
+        Ok(())
+    }
+
+    /// Takes back the last move and returns its cell.
+    pub fn undo(&mut self) -> Option<Path> {
+        let path = self.history.undo()?;
+
+        self.board.remove_token(&path);
+        self.current_player = self.current_player.other();
+
+        Some(path)
+    }
+
+    /// Plays the last move which was taken back again and returns its cell.
+    pub fn redo(&mut self) -> Option<Path> {
+        let path = self.history.redo()?;
+        self.place(&path);
+
+        Some(path)
+    }
+
+    fn place(&mut self, path: &Path) {
+        self.board.place_token(path, self.current_player.into());
+        self.current_player = self.current_player.other();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game;
+    use recursive::*;
+
+    fn path(indices: &[usize]) -> Path {
+        Path::new(
+            indices
+                .iter()
+                .map(|&i| coord::Local::from_index(i))
+                .collect(),
+        )
+    }
+
+    /// Plays the same moves on both implementations, each number picking a legal move.
+    #[quickcheck]
+    fn depth_two_agrees_with_game(choices: Vec<u8>) -> bool {
+        let mut standard = game::Game::new();
+        let mut recursive = Game::new(2);
+        for choice in choices {
+            let moves: Vec<coord::Global> = standard.legal_moves().collect();
+            let recursive_moves: Vec<Path> = recursive.legal_moves().collect();
+            let converted: Vec<Path> = moves.iter().map(|&p| p.into()).collect();
+            if recursive_moves != converted || recursive.outcome() != standard.outcome() {
+                return false;
+            }
+            if moves.is_empty() {
+                break;
+            }
+            let position = moves[choice as usize % moves.len()];
+            standard.play(position).unwrap();
+            recursive.play(position.into()).unwrap();
+        }
+        true
+    }
+
+    #[test]
+    fn moves_send_to_the_innermost_board() {
+        let mut game = Game::default();
+        assert_eq!(game.legal_moves().count(), 729);
+
+        game.play(path(&[4, 2, 7])).unwrap();
+        assert_eq!(game.active_board(), path(&[2, 7]).squares());
+        assert_eq!(game.legal_moves().count(), 9);
+        assert_eq!(game.play(path(&[2, 6, 0])), Err(IllegalMove::WrongRegion));
+        game.play(path(&[2, 7, 0])).unwrap();
+        assert_eq!(game.active_board(), path(&[7, 0]).squares());
+
+        game.undo();
+        assert_eq!(game.active_board(), path(&[2, 7]).squares());
+    }
+
+    #[test]
+    fn decided_boards_free_the_enclosing_board() {
+        let mut game = Game::default();
+        for &cell in &[3, 4, 5] {
+            game.board.place_token(&path(&[0, 0, cell]), Token::Circle);
+        }
+        assert_eq!(
+            game.board().owner(path(&[0, 0]).squares()),
+            Ownership::Circle
+        );
+
+        // Circle is sent to the decided [0, 0], so it may play anywhere else in [0].
+        game.play(path(&[1, 0, 0])).unwrap();
+        assert_eq!(game.active_board(), path(&[0]).squares());
+        assert_eq!(game.legal_moves().count(), 8 * 9);
+        assert!(!game.can_place_in(path(&[0, 0]).squares()));
+        assert!(game.can_place_in(path(&[0, 1]).squares()));
+        assert!(!game.can_place_in(path(&[1]).squares()));
+    }
+
+    #[test]
+    fn wins_propagate_to_the_outermost_board() {
+        let mut board = Board::default();
+        for &outer in &[0, 4] {
+            for &middle in &[0, 1, 2] {
+                for &inner in &[3, 4, 5] {
+                    board.place_token(&path(&[outer, middle, inner]), Token::Cross);
+                }
+            }
+            assert_eq!(board.owner(path(&[outer]).squares()), Ownership::Cross);
+            assert_eq!(board.total(), Ownership::Undecided);
+        }
+
+        for &middle in &[2, 4, 6] {
+            for &inner in &[0, 4, 8] {
+                board.place_token(&path(&[8, middle, inner]), Token::Cross);
+            }
+        }
+        assert_eq!(board.total(), Ownership::Cross);
+        assert_eq!(
+            board
+                .winning_line(&[])
+                .map(|line| line.iter().map(|s| s.index()).collect()),
+            Some(vec![0, 4, 8])
+        );
+        assert_eq!(
+            board
+                .winning_line(path(&[8, 6]).squares())
+                .map(|line| line.iter().map(|s| s.index()).collect()),
+            Some(vec![0, 4, 8])
+        );
+        assert_eq!(board.winning_line(path(&[8, 6, 8]).squares()), None);
+
+        board.remove_token(&path(&[8, 6, 8]));
+        assert_eq!(board.owner(path(&[8, 6]).squares()), Ownership::Undecided);
+        assert_eq!(board.owner(path(&[8]).squares()), Ownership::Undecided);
+        assert_eq!(board.total(), Ownership::Undecided);
+    }
+}
@@ -0,0 +1,243 @@
+//! Rule variants played by different communities. `Rules::default()` gives the rules
+//! used everywhere else in this crate unless a game is started with other rules.
+//!
+//! Rules are written as a comma separated list of the variants which differ from the
+//! default, like `free-move-anywhere,majority`, or as `standard` for the default rules.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Where a player may move after being sent to a decided region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreeMove {
+    /// Into any undecided region.
+    UndecidedRegion,
+    /// Into any clear cell, even in decided regions. Tokens placed into a decided region
+    /// don't change its owner.
+    AnyRegion,
+}
+
+/// Who owns a drawn region on the meta-board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawnRegions {
+    Nobody,
+    /// A drawn region completes lines for either player, but a line needs at least one
+    /// region the player actually won. If a drawn region completes lines for both
+    /// players at once, the player who made the move wins.
+    Both,
+}
+
+/// What happens when every region is decided, but nobody has three in a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullBoard {
+    Draw,
+    /// The player who won more regions wins. Equal counts are a draw.
+    Majority,
+}
+
+/// Whether completing a line is good or bad for the player who completes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Misere {
+    /// Completing a line wins, as usual.
+    Off,
+    /// The player who would win by the other rules loses instead, so completing three
+    /// regions in a row loses the game. Lines inside a region still capture it.
+    Game,
+    /// Like `Game`, but a line inside a region also gives the region to the opponent.
+    Regions,
+}
+
+/// What happens when neither player can complete a line of regions anymore, see
+/// `board::Global::is_dead`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadGame {
+    /// The game goes on until every region is decided.
+    PlayOn,
+    /// The game ends as a draw right away.
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub free_move: FreeMove,
+    pub drawn_regions: DrawnRegions,
+    pub full_board: FullBoard,
+    pub misere: Misere,
+    pub dead_game: DeadGame,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            free_move: FreeMove::UndecidedRegion,
+            drawn_regions: DrawnRegions::Nobody,
+            full_board: FullBoard::Draw,
+            misere: Misere::Off,
+            dead_game: DeadGame::PlayOn,
+        }
+    }
+}
+
+impl Rules {
+    /// Whether a line inside a region gives the region to the opponent of its player.
+    pub fn inverts_captures(self) -> bool {
+        self.misere == Misere::Regions
+    }
+}
+
+/// The names of the variants, in the order they are written.
+const FREE_MOVE_ANYWHERE: &str = "free-move-anywhere";
+const DRAWS_FOR_BOTH: &str = "draws-for-both";
+const MAJORITY: &str = "majority";
+const MISERE: &str = "misere";
+const MISERE_REGIONS: &str = "misere-regions";
+const EARLY_DRAW: &str = "early-draw";
+const STANDARD: &str = "standard";
+
+/// An unknown rule variant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseRulesError(pub String);
+
+impl fmt::Display for ParseRulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown rule '{}', expected '{}' or a list of '{}', '{}', '{}', '{}' or '{}' and '{}'",
+            self.0,
+            STANDARD,
+            FREE_MOVE_ANYWHERE,
+            DRAWS_FOR_BOTH,
+            MAJORITY,
+            MISERE,
+            MISERE_REGIONS,
+            EARLY_DRAW
+        )
+    }
+}
+
+impl Error for ParseRulesError {}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut variants = vec![];
+        if self.free_move == FreeMove::AnyRegion {
+            variants.push(FREE_MOVE_ANYWHERE);
+        }
+        if self.drawn_regions == DrawnRegions::Both {
+            variants.push(DRAWS_FOR_BOTH);
+        }
+        if self.full_board == FullBoard::Majority {
+            variants.push(MAJORITY);
+        }
+        match self.misere {
+            Misere::Off => {}
+            Misere::Game => variants.push(MISERE),
+            Misere::Regions => variants.push(MISERE_REGIONS),
+        }
+        if self.dead_game == DeadGame::Draw {
+            variants.push(EARLY_DRAW);
+        }
+
+        if variants.is_empty() {
+            write!(f, "{}", STANDARD)
+        } else {
+            write!(f, "{}", variants.join(","))
+        }
+    }
+}
+
+impl FromStr for Rules {
+    type Err = ParseRulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::default();
+        if s.trim() == STANDARD {
+            return Ok(rules);
+        }
+        for variant in s.split(',').map(str::trim) {
+            match variant {
+                FREE_MOVE_ANYWHERE => rules.free_move = FreeMove::AnyRegion,
+                DRAWS_FOR_BOTH => rules.drawn_regions = DrawnRegions::Both,
+                MAJORITY => rules.full_board = FullBoard::Majority,
+                // Inverted captures include the inverted outcome.
+                MISERE if rules.misere == Misere::Off => rules.misere = Misere::Game,
+                MISERE => {}
+                MISERE_REGIONS => rules.misere = Misere::Regions,
+                EARLY_DRAW => rules.dead_game = DeadGame::Draw,
+                _ => return Err(ParseRulesError(variant.to_string())),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::{Arbitrary, Gen};
+    use rules::*;
+
+    impl Arbitrary for Rules {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            Rules {
+                free_move: if g.gen() {
+                    FreeMove::AnyRegion
+                } else {
+                    FreeMove::UndecidedRegion
+                },
+                drawn_regions: if g.gen() {
+                    DrawnRegions::Both
+                } else {
+                    DrawnRegions::Nobody
+                },
+                full_board: if g.gen() {
+                    FullBoard::Majority
+                } else {
+                    FullBoard::Draw
+                },
+                misere: match g.gen(): u8 % 3 {
+                    0 => Misere::Off,
+                    1 => Misere::Game,
+                    _ => Misere::Regions,
+                },
+                dead_game: if g.gen() {
+                    DeadGame::Draw
+                } else {
+                    DeadGame::PlayOn
+                },
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn rules_round_trip(rules: Rules) -> bool {
+        rules.to_string().parse() == Ok(rules)
+    }
+
+    #[test]
+    fn parse_rules() {
+        assert_eq!(Rules::default().to_string(), "standard");
+        assert_eq!(
+            "majority, free-move-anywhere".parse(),
+            Ok(Rules {
+                free_move: FreeMove::AnyRegion,
+                full_board: FullBoard::Majority,
+                ..Rules::default()
+            })
+        );
+        assert_eq!(
+            "misere-regions,misere"
+                .parse::<Rules>()
+                .map(|rules| rules.misere),
+            Ok(Misere::Regions)
+        );
+        assert_eq!(
+            "early-draw".parse::<Rules>().map(|rules| rules.dead_game),
+            Ok(DeadGame::Draw)
+        );
+        assert_eq!(
+            "majority,standard".parse::<Rules>(),
+            Err(ParseRulesError("standard".to_string()))
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Ultimate Tic-Tac-Toe on other board sizes: `n` × `n` regions of `n` × `n` cells each,
+//! where `k` tokens in a row capture a region and `k` regions in a row win the game.
+//!
+//! The lines, how a grid is evaluated and the move history are the same as in the
+//! standard 3 × 3 game, from `board` and `history`. That game in `game::Game` keeps its
+//! regions in fixed arrays, which makes it much faster. It also supports the rule variants
+//! and is the one used by the computer opponents. Games on other sizes are played by the
+//! default rules.
+
+use board::{self, Ownership, Player, Token};
+use coord;
+use game::IllegalMove;
+use history::History;
+use std::error::Error;
+use std::fmt;
+
+/// The largest supported number of regions per side.
+pub const MAX_SIZE: u8 = 5;
+
+/// The number of squares per side and how many in a row are needed for a line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Size {
+    n: u8,
+    k: u8,
+}
+
+/// Sizes which can't be played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeError {
+    /// The board is smaller than 2 × 2 or larger than `MAX_SIZE`.
+    InvalidSize(u8),
+    /// A line must be at least 2 and at most `n` long.
+    InvalidLine { n: u8, k: u8 },
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SizeError::InvalidSize(n) => write!(
+                f,
+                "the board size must be between 2 and {}, not {}",
+                MAX_SIZE, n
+            ),
+            SizeError::InvalidLine { n, k } => write!(
+                f,
+                "a line on a board of size {} must be between 2 and {} long, not {}",
+                n, n, k
+            ),
+        }
+    }
+}
+
+impl Error for SizeError {}
+
+/// A square on an `n` × `n` grid, which is either a region or a cell inside a region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Square {
+    pub x: u8,
+    pub y: u8,
+}
+
+/// A cell on the whole board, given by its region and its place inside the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub region: Square,
+    pub cell: Square,
+}
+
+/// The tokens, region owners and the outcome of a game of any size.
+#[derive(Clone, Debug)]
+pub struct Board {
+    size: Size,
+    /// The lines of the grid, as indices of squares.
+    lines: Vec<Vec<usize>>,
+    /// The cells of every region, one region after the other.
+    tokens: Vec<Token>,
+    regions: Vec<Ownership>,
+    total: Ownership,
+}
+
+/// A game on a board of any size, following the default rules of `game::Game`. None of
+/// the variants in `rules::Rules` are supported, so there is no way to pass them in.
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: Board,
+    current_player: Player,
+    active_region: Option<Square>,
+    history: History<Turn>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Turn {
+    position: Position,
+    active_region: Option<Square>,
+}
+
+impl Default for Size {
+    fn default() -> Self {
+        Size { n: 3, k: 3 }
+    }
+}
+
+impl Size {
+    pub fn new(n: u8, k: u8) -> Result<Self, SizeError> {
+        if n < 2 || n > MAX_SIZE {
+            Err(SizeError::InvalidSize(n))
+        } else if k < 2 || k > n {
+            Err(SizeError::InvalidLine { n, k })
+        } else {
+            Ok(Size { n, k })
+        }
+    }
+
+    /// The number of squares per side.
+    pub fn n(self) -> u8 {
+        self.n
+    }
+
+    /// The number of squares in a row needed for a line.
+    pub fn k(self) -> u8 {
+        self.k
+    }
+
+    /// All squares of the grid, row by row.
+    pub fn squares(self) -> impl Iterator<Item = Square> {
+        let n = self.n;
+        (0..n * n).map(move |i| Square::new(i % n, i / n))
+    }
+
+    /// All positions of the board, region by region.
+    pub fn positions(self) -> impl Iterator<Item = Position> {
+        self.squares()
+            .flat_map(move |region| self.squares().map(move |cell| Position::new(region, cell)))
+    }
+}
+
+impl Square {
+    pub fn new(x: u8, y: u8) -> Self {
+        Square { x, y }
+    }
+
+    /// The index of the square when the squares are counted row by row.
+    pub fn index(self, size: Size) -> usize {
+        assert!(self.x < size.n && self.y < size.n);
+        self.y as usize * size.n as usize + self.x as usize
+    }
+}
+
+impl From<coord::Local> for Square {
+    fn from(local: coord::Local) -> Self {
+        Square::new(local.get_x(), local.get_y())
+    }
+}
+
+impl Position {
+    pub fn new(region: Square, cell: Square) -> Self {
+        Position { region, cell }
+    }
+}
+
+impl From<coord::Global> for Position {
+    fn from(position: coord::Global) -> Self {
+        Position::new(position.get_region().into(), position.get_local().into())
+    }
+}
+
+impl Board {
+    pub fn new(size: Size) -> Self {
+        let regions = size.n as usize * size.n as usize;
+        Board {
+            size,
+            lines: board::lines(size.n, size.k),
+            tokens: vec![Token::Clear; regions * regions],
+            regions: vec![Ownership::Undecided; regions],
+            total: Ownership::Undecided,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    fn index(&self, position: Position) -> usize {
+        let squares = self.size.n as usize * self.size.n as usize;
+        position.region.index(self.size) * squares + position.cell.index(self.size)
+    }
+
+    pub fn token(&self, position: Position) -> Token {
+        self.tokens[self.index(position)]
+    }
+
+    pub fn owner(&self, region: Square) -> Ownership {
+        self.regions[region.index(self.size)]
+    }
+
+    /// Who owns the meta-board. Anything but `Ownership::Undecided` means the game is over.
+    pub fn total(&self) -> Ownership {
+        self.total
+    }
+
+    /// Whether neither player can complete a line in the region anymore, because every
+    /// line holds tokens of both players. See `board::Local::is_dead`.
+    pub fn is_dead(&self, region: Square) -> bool {
+        let squares = self.size.n as usize * self.size.n as usize;
+        let region = region.index(self.size);
+        let cells = &self.tokens[region * squares..(region + 1) * squares];
+        !board::can_complete_line(&self.lines, Token::Cross, |i| cells[i])
+            && !board::can_complete_line(&self.lines, Token::Circle, |i| cells[i])
+    }
+
+    /// The regions which won the game, if any.
+    pub fn winning_line(&self) -> Option<Vec<Square>> {
+        self.find_line(self.total, |i| self.regions[i])
+    }
+
+    /// The cells which captured the region, if it is captured.
+    pub fn region_winning_line(&self, region: Square) -> Option<Vec<Square>> {
+        let squares = self.size.n as usize * self.size.n as usize;
+        let index = region.index(self.size);
+        let cells = &self.tokens[index * squares..(index + 1) * squares];
+        self.find_line(self.regions[index], |i| cells[i].into())
+    }
+
+    /// A line of a grid where every square belongs to the owner.
+    fn find_line<F: Fn(usize) -> Ownership>(
+        &self,
+        owner: Ownership,
+        squares: F,
+    ) -> Option<Vec<Square>> {
+        let n = self.size.n;
+        board::find_line(&self.lines, owner, squares).map(|line| {
+            line.iter()
+                .map(|&i| Square::new(i as u8 % n, i as u8 / n))
+                .collect()
+        })
+    }
+
+    /// Places a token into a clear cell of an undecided region.
+    pub fn place_token(&mut self, position: Position, token: Token) {
+        let index = self.index(position);
+        self.tokens[index] = token;
+        self.update_region(position.region);
+    }
+
+    /// Reverts `place_token` for the most recently placed token.
+    pub fn remove_token(&mut self, position: Position) {
+        let index = self.index(position);
+        self.tokens[index] = Token::Clear;
+        self.update_region(position.region);
+    }
+
+    fn update_region(&mut self, region: Square) {
+        let squares = self.size.n as usize * self.size.n as usize;
+        let region = region.index(self.size);
+        let cells = &self.tokens[region * squares..(region + 1) * squares];
+        self.regions[region] = board::evaluate_grid(&self.lines, squares, |i| cells[i].into());
+        let regions = &self.regions;
+        self.total = board::evaluate_grid(&self.lines, squares, |i| regions[i]);
+    }
+}
+
+impl Game {
+    pub fn new(size: Size) -> Self {
+        Game {
+            board: Board::new(size),
+            current_player: Player::Cross,
+            active_region: None,
+            history: History::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.board.size
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn current_player(&self) -> Player {
+        self.current_player
+    }
+
+    /// The region the current player is forced to play in, `None` for a free move.
+    pub fn active_region(&self) -> Option<Square> {
+        self.active_region
+    }
+
+    pub fn outcome(&self) -> Ownership {
+        self.board.total
+    }
+
+    /// A region is playable if it is undecided and the current player is either sent there
+    /// or has a free move.
+    pub fn can_place_in_region(&self, region: Square) -> bool {
+        let is_active = self.active_region == None || self.active_region == Some(region);
+        is_active && self.board.owner(region) == Ownership::Undecided
+    }
+
+    /// Checks if the current player may place a token at the given position.
+    pub fn check(&self, position: Position) -> Result<(), IllegalMove> {
+        if self.outcome() != Ownership::Undecided {
+            Err(IllegalMove::GameOver)
+        } else if !self.can_place_in_region(position.region) {
+            Err(IllegalMove::WrongRegion)
+        } else if self.board.token(position) != Token::Clear {
+            Err(IllegalMove::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// All positions the current player may place a token at, region by region.
+    pub fn legal_moves<'a>(&'a self) -> impl Iterator<Item = Position> + 'a {
+        self.size()
+            .positions()
+            .filter(move |&position| self.check(position).is_ok())
+    }
+
+    /// All moves played so far, starting with the first one.
+    pub fn moves<'a>(&'a self) -> impl Iterator<Item = Position> + 'a {
+        self.history.played().iter().map(|turn| turn.position)
+    }
+
+    /// Places a token for the current player and discards all moves which could be redone.
+    pub fn play(&mut self, position: Position) -> Result<(), IllegalMove> {
+        self.check(position)?;
+        self.history.play(Turn {
+            position,
+            active_region: self.active_region,
+        });
+        self.place(position);
+
+        Ok(())
+    }
+
+    /// Takes back the last move and returns its position.
+    pub fn undo(&mut self) -> Option<Position> {
+        let turn = self.history.undo()?;
+
+        self.board.remove_token(turn.position);
+        self.current_player = self.current_player.other();
+        self.active_region = turn.active_region;
+
+        Some(turn.position)
+    }
+
+    /// Plays the last move which was taken back again and returns its position.
+    pub fn redo(&mut self) -> Option<Position> {
+        let turn = self.history.redo()?;
+        self.place(turn.position);
+
+        Some(turn.position)
+    }
+
+    fn place(&mut self, position: Position) {
+        self.board.place_token(position, self.current_player.into());
+        self.current_player = self.current_player.other();
+        if self.board.owner(position.cell) == Ownership::Undecided {
+            self.active_region = Some(position.cell);
+        } else {
+            self.active_region = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game;
+    use sized::*;
+
+    #[test]
+    fn reject_invalid_sizes() {
+        assert_eq!(Size::new(1, 1), Err(SizeError::InvalidSize(1)));
+        assert_eq!(Size::new(6, 3), Err(SizeError::InvalidSize(6)));
+        assert_eq!(Size::new(4, 5), Err(SizeError::InvalidLine { n: 4, k: 5 }));
+        assert_eq!(Size::new(4, 1), Err(SizeError::InvalidLine { n: 4, k: 1 }));
+    }
+
+    /// Plays the same moves on both implementations, each number picking a legal move.
+    #[quickcheck]
+    fn standard_size_agrees_with_game(choices: Vec<u8>) -> bool {
+        let mut standard = game::Game::new();
+        let mut sized = Game::new(Size::default());
+        for choice in choices {
+            let moves: Vec<coord::Global> = standard.legal_moves().collect();
+            let sized_moves: Vec<Position> = sized.legal_moves().collect();
+            let converted: Vec<Position> = moves.iter().map(|&p| p.into()).collect();
+            if sized_moves != converted || sized.outcome() != standard.outcome() {
+                return false;
+            }
+            if moves.is_empty() {
+                break;
+            }
+            let position = moves[choice as usize % moves.len()];
+            standard.play(position).unwrap();
+            sized.play(position.into()).unwrap();
+        }
+        true
+    }
+
+    #[test]
+    fn play_four_by_four() {
+        let size = Size::new(4, 3).unwrap();
+        let mut game = Game::new(size);
+        assert_eq!(game.legal_moves().count(), 256);
+
+        let at = |rx, ry, x, y| Position::new(Square::new(rx, ry), Square::new(x, y));
+        game.play(at(0, 0, 3, 3)).unwrap();
+        assert_eq!(game.active_region(), Some(Square::new(3, 3)));
+        assert_eq!(game.legal_moves().count(), 16);
+        assert_eq!(game.play(at(0, 0, 0, 0)), Err(IllegalMove::WrongRegion));
+
+        // Circle is sent back to region (3, 3) until it has three in a row there.
+        game.play(at(3, 3, 1, 0)).unwrap();
+        game.play(at(1, 0, 3, 3)).unwrap();
+        game.play(at(3, 3, 2, 0)).unwrap();
+        game.play(at(2, 0, 3, 3)).unwrap();
+        game.play(at(3, 3, 3, 0)).unwrap();
+        assert_eq!(game.board().owner(Square::new(3, 3)), Ownership::Circle);
+        assert_eq!(game.active_region(), Some(Square::new(3, 0)));
+        assert_eq!(
+            game.board().region_winning_line(Square::new(3, 3)),
+            Some(vec![
+                Square::new(1, 0),
+                Square::new(2, 0),
+                Square::new(3, 0)
+            ])
+        );
+
+        game.undo();
+        assert_eq!(game.board().owner(Square::new(3, 3)), Ownership::Undecided);
+        assert_eq!(game.board().region_winning_line(Square::new(3, 3)), None);
+    }
+
+    #[test]
+    fn k_regions_in_a_row_win() {
+        let size = Size::new(2, 2).unwrap();
+        let mut board = Board::new(size);
+        let at = |rx, ry, x, y| Position::new(Square::new(rx, ry), Square::new(x, y));
+        for &(rx, ry) in &[(0, 0), (1, 1)] {
+            board.place_token(at(rx, ry, 0, 0), Token::Circle);
+            assert_eq!(board.total(), Ownership::Undecided);
+            board.place_token(at(rx, ry, 1, 0), Token::Circle);
+        }
+        assert_eq!(board.total(), Ownership::Circle);
+        assert_eq!(
+            board.winning_line(),
+            Some(vec![Square::new(0, 0), Square::new(1, 1)])
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! A fixed-size transposition table, which remembers search results by position hash.
+//! Searches look positions up before exploring them, so a position reached by several
+//! move orders is only searched once.
+
+/// A search result stored for a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry<T> {
+    /// The full hash, which tells apart positions sharing a slot.
+    pub hash: u64,
+    /// How many moves ahead the position was searched.
+    pub depth: u32,
+    pub value: T,
+}
+
+/// A hash table with a fixed number of slots, where each hash maps to exactly one slot.
+/// If two positions compete for a slot, the deeper search is kept.
+#[derive(Clone, Debug)]
+pub struct TranspositionTable<T> {
+    slots: Vec<Option<Entry<T>>>,
+}
+
+impl<T: Copy> TranspositionTable<T> {
+    /// Creates an empty table. The size is rounded up to a power of two.
+    pub fn new(size: usize) -> Self {
+        TranspositionTable {
+            slots: vec![None; size.max(1).next_power_of_two()],
+        }
+    }
+
+    /// The number of slots.
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, hash: u64) -> usize {
+        hash as usize & (self.slots.len() - 1)
+    }
+
+    /// Looks up the result stored for the position with the given hash.
+    pub fn get(&self, hash: u64) -> Option<&Entry<T>> {
+        self.slots[self.slot(hash)]
+            .as_ref()
+            .filter(|entry| entry.hash == hash)
+    }
+
+    /// Stores a result, unless its slot already holds a deeper search.
+    pub fn insert(&mut self, hash: u64, depth: u32, value: T) {
+        let slot = self.slot(hash);
+        let replace = match self.slots[slot] {
+            Some(ref entry) => depth >= entry.depth,
+            None => true,
+        };
+        if replace {
+            self.slots[slot] = Some(Entry { hash, depth, value });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            *slot = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use transposition::*;
+
+    #[test]
+    fn size_is_rounded_up() {
+        assert_eq!(TranspositionTable::<u8>::new(1000).size(), 1024);
+        assert_eq!(TranspositionTable::<u8>::new(0).size(), 1);
+    }
+
+    #[test]
+    fn finds_stored_entries() {
+        let mut table = TranspositionTable::new(16);
+        table.insert(3, 2, 'a');
+        assert_eq!(table.get(3).map(|entry| entry.value), Some('a'));
+        assert_eq!(table.get(4), None);
+        // Same slot, but a different position.
+        assert_eq!(table.get(19), None);
+
+        table.clear();
+        assert_eq!(table.get(3), None);
+    }
+
+    #[test]
+    fn deeper_searches_are_kept() {
+        let mut table = TranspositionTable::new(16);
+        table.insert(3, 4, 'a');
+        table.insert(19, 2, 'b');
+        assert_eq!(table.get(3).map(|entry| entry.value), Some('a'));
+        assert_eq!(table.get(19), None);
+
+        table.insert(19, 4, 'c');
+        assert_eq!(table.get(3), None);
+        assert_eq!(table.get(19).map(|entry| entry.value), Some('c'));
+    }
+}
@@ -0,0 +1,368 @@
+//! Checks whether a position could have come up in a real game. Imported or hand-made
+//! positions can contain token counts, owners or forced regions which no sequence of legal
+//! moves leads to. `generate::random_game` only produces positions which pass the checks.
+//!
+//! The checks are necessary, but not sufficient: a position without problems isn't
+//! guaranteed to be reachable, but a position with problems never is.
+
+use board::{self, Ownership, Player, Token};
+use coord;
+use game::Game;
+use rules::FreeMove;
+use std::fmt;
+
+/// One reason why a position can't be reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Cross moves first, so it has as many tokens as circle or one more.
+    TokenCounts { cross: usize, circle: usize },
+    /// The player to move doesn't follow from the token counts.
+    WrongPlayer { expected: Player },
+    /// The stored owner of a region doesn't follow from its cells.
+    RegionOwner {
+        region: coord::Local,
+        stored: Ownership,
+        expected: Ownership,
+    },
+    /// Tokens were placed into a region after it was captured, which the rules don't allow.
+    PlayedAfterCapture { region: coord::Local },
+    /// The stored owner of the meta-board doesn't follow from the owners of the regions.
+    MetaOwner {
+        stored: Ownership,
+        expected: Ownership,
+    },
+    /// The game was already decided before the last move.
+    PlayedAfterGameOver,
+    /// The player to move is sent to a decided region, which gives a free move instead.
+    ForcedIntoDecidedRegion { region: coord::Local },
+    /// No token of the last player could have sent the player to move where they are sent,
+    /// either to the forced region or to a decided region for a free move.
+    UnreachableActiveRegion { active_region: Option<coord::Local> },
+}
+
+/// How the messages name an owner.
+fn describe(owner: Ownership) -> &'static str {
+    match owner {
+        Ownership::Undecided => "undecided",
+        Ownership::Cross => "owned by cross",
+        Ownership::Circle => "owned by circle",
+        Ownership::Draw => "drawn",
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::TokenCounts { cross, circle } => write!(
+                f,
+                "cross has {} tokens and circle {}, but cross has to have as many or one more",
+                cross, circle
+            ),
+            Problem::WrongPlayer { expected } => {
+                write!(f, "the token counts require {:?} to move", expected)
+            }
+            Problem::RegionOwner {
+                region,
+                stored,
+                expected,
+            } => write!(
+                f,
+                "region {} is {}, but its cells make it {}",
+                region.index() + 1,
+                describe(stored),
+                describe(expected)
+            ),
+            Problem::PlayedAfterCapture { region } => write!(
+                f,
+                "tokens were placed into region {} after it was captured",
+                region.index() + 1
+            ),
+            Problem::MetaOwner { stored, expected } => write!(
+                f,
+                "the game is {}, but the regions make it {}",
+                describe(stored),
+                describe(expected)
+            ),
+            Problem::PlayedAfterGameOver => write!(f, "moves were played after the game was over"),
+            Problem::ForcedIntoDecidedRegion { region } => write!(
+                f,
+                "the player is sent to region {}, which is already decided",
+                region.index() + 1
+            ),
+            Problem::UnreachableActiveRegion { active_region } => match active_region {
+                Some(region) => write!(
+                    f,
+                    "no move of the last player sends the player to region {}",
+                    region.index() + 1
+                ),
+                None => write!(f, "no move of the last player gives a free move"),
+            },
+        }
+    }
+}
+
+/// Finds every problem with the position of the game, see `validate_position`.
+pub fn validate(game: &Game) -> Vec<Problem> {
+    validate_position(game.board(), game.current_player(), game.active_region())
+}
+
+/// Finds every problem with the position, where the active region has the same meaning as
+/// in `game::Game`. An empty list means the position passed all checks.
+pub fn validate_position(
+    board: &board::Global,
+    current_player: Player,
+    active_region: Option<coord::Local>,
+) -> Vec<Problem> {
+    let mut problems = vec![];
+    let last_player = current_player.other();
+
+    let count = |token| {
+        coord::Global::iter()
+            .filter(|&position| board[position] == token)
+            .count()
+    };
+    let (cross, circle) = (count(Token::Cross), count(Token::Circle));
+    if cross != circle && cross != circle + 1 {
+        problems.push(Problem::TokenCounts { cross, circle });
+    } else {
+        let expected = if cross == circle {
+            Player::Cross
+        } else {
+            Player::Circle
+        };
+        if expected != current_player {
+            problems.push(Problem::WrongPlayer { expected });
+        }
+    }
+
+    let rules = board.rules();
+    for region in coord::Local::iter() {
+        let local = &board[region];
+        if let Some(problem) = check_region(local, region, rules.inverts_captures()) {
+            problems.push(problem);
+        }
+        // Only free moves into decided regions allow tokens after the capture.
+        if rules.free_move == FreeMove::UndecidedRegion && was_played_after_capture(local) {
+            problems.push(Problem::PlayedAfterCapture { region });
+        }
+    }
+
+    let last_owner: Ownership = Token::from(last_player).into();
+    let expected = board.evaluate_total(last_owner);
+    if board.total() != expected {
+        problems.push(Problem::MetaOwner {
+            stored: board.total(),
+            expected,
+        });
+    }
+    if expected != Ownership::Undecided && !was_decided_by_last_move(board, last_player) {
+        problems.push(Problem::PlayedAfterGameOver);
+    }
+
+    if let Some(region) = active_region {
+        if board[region].total != Ownership::Undecided {
+            problems.push(Problem::ForcedIntoDecidedRegion { region });
+        }
+    }
+    if !sends_to(board, last_player, active_region) {
+        problems.push(Problem::UnreachableActiveRegion { active_region });
+    }
+
+    problems
+}
+
+/// Compares the stored owner of the region with its cells. A captured region needs a line
+/// of the player who captured it, which is the opponent of the owner if captures are
+/// inverted.
+fn check_region(local: &board::Local, region: coord::Local, inverts: bool) -> Option<Problem> {
+    let stored = local.total;
+    let consistent = match stored {
+        Ownership::Undecided | Ownership::Draw => local.evaluate() == stored,
+        Ownership::Cross | Ownership::Circle => {
+            let capturer = if inverts { stored.inverted() } else { stored };
+            local.has_line(capturer.into())
+        }
+    };
+    if consistent {
+        None
+    } else {
+        let expected = if inverts {
+            local.evaluate().inverted()
+        } else {
+            local.evaluate()
+        };
+        Some(Problem::RegionOwner {
+            region,
+            stored,
+            expected,
+        })
+    }
+}
+
+/// A region with a line was captured by one of the tokens in it. If no single token of
+/// that line can be taken back to undo the capture, play continued afterwards.
+fn was_played_after_capture(local: &board::Local) -> bool {
+    let capturer: Token = match local.evaluate() {
+        Ownership::Undecided | Ownership::Draw => return false,
+        owner => owner.into(),
+    };
+    !coord::Local::iter()
+        .filter(|&cell| local[cell] == capturer)
+        .any(|cell| {
+            let mut before = local.clone();
+            before.remove_token(cell);
+            before.evaluate() == Ownership::Undecided
+        })
+}
+
+/// Whether taking back one of the last player's tokens leaves the game undecided.
+fn was_decided_by_last_move(board: &board::Global, last_player: Player) -> bool {
+    let token: Token = last_player.into();
+    coord::Global::iter()
+        .filter(|&position| board[position] == token)
+        .any(|position| {
+            let mut before = board.clone();
+            before.remove_token(position);
+            before.evaluate_total(token.into()) == Ownership::Undecided
+        })
+}
+
+/// Whether some token of the last player sends the player to move to the active region,
+/// or to a decided region for a free move. Without any tokens, the first move is free.
+fn sends_to(
+    board: &board::Global,
+    last_player: Player,
+    active_region: Option<coord::Local>,
+) -> bool {
+    let token: Token = last_player.into();
+    let cells: Vec<coord::Local> = coord::Global::iter()
+        .filter(|&position| board[position] == token)
+        .map(coord::Global::get_local)
+        .collect();
+    if cells.is_empty() {
+        return active_region.is_none();
+    }
+    match active_region {
+        Some(region) => cells.contains(&region),
+        None => cells
+            .iter()
+            .any(|&cell| board[cell].total != Ownership::Undecided),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use generate::{random_game, Bias};
+    use rules::Rules;
+    use validate::*;
+
+    /// Games which avoid giving away regions last longer, so they fill more of the board
+    /// than the uniform games in `generate`'s tests.
+    #[quickcheck]
+    fn played_games_are_valid(rules: Rules, moves: u8, seed: u64) -> bool {
+        let game = random_game(rules, moves as usize, Bias::AvoidRegionLosses, seed);
+        validate(&game).is_empty()
+    }
+
+    #[test]
+    fn messages_name_the_owners() {
+        let problem = Problem::RegionOwner {
+            region: coord::Local::new(0, 0),
+            stored: Ownership::Undecided,
+            expected: Ownership::Cross,
+        };
+        assert_eq!(
+            problem.to_string(),
+            "region 1 is undecided, but its cells make it owned by cross"
+        );
+    }
+
+    #[test]
+    fn filled_boards_are_invalid() {
+        let mut board = board::Global::new();
+        for position in coord::Global::iter() {
+            board[position] = if position.get_local().index() % 2 == 0 {
+                Token::Cross
+            } else {
+                Token::Circle
+            };
+        }
+        let problems = validate_position(&board, Player::Cross, None);
+        // Every region is full, but still stored as undecided.
+        let owners = problems
+            .iter()
+            .filter(|problem| match problem {
+                Problem::RegionOwner { .. } => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(owners, 9);
+    }
+
+    #[test]
+    fn check_token_counts() {
+        let game: Game = "XX......./........./........./........./........./\
+                          ........./........./........./......... O -"
+            .parse()
+            .unwrap();
+        assert!(validate(&game).contains(&Problem::TokenCounts {
+            cross: 2,
+            circle: 0
+        }));
+
+        let game: Game = "X......../........./........./........./........./\
+                          ........./........./........./......... X 1"
+            .parse()
+            .unwrap();
+        assert!(validate(&game).contains(&Problem::WrongPlayer {
+            expected: Player::Circle
+        }));
+    }
+
+    #[test]
+    fn check_active_region() {
+        // Cross played into the top left cell, so circle is sent to the top left region.
+        let game: Game = "X......../........./........./........./........./\
+                          ........./........./........./......... O 1"
+            .parse()
+            .unwrap();
+        assert_eq!(validate(&game), vec![]);
+
+        let game: Game = "X......../........./........./........./........./\
+                          ........./........./........./......... O 2"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            validate(&game),
+            vec![Problem::UnreachableActiveRegion {
+                active_region: Some(coord::Local::new(1, 0))
+            }]
+        );
+    }
+
+    #[test]
+    fn check_play_after_capture() {
+        // Both players completed a second line after capturing the region.
+        let game: Game = "XXXXXX.../OOOOOO.../........./........./........./\
+                          ........./........./........./......... X -"
+            .parse()
+            .unwrap();
+        let problems = validate(&game);
+        assert!(problems.contains(&Problem::PlayedAfterCapture {
+            region: coord::Local::new(0, 0)
+        }));
+        assert!(problems.contains(&Problem::PlayedAfterCapture {
+            region: coord::Local::new(1, 0)
+        }));
+    }
+
+    #[test]
+    fn check_play_after_game_over() {
+        // Circle won the game with three regions in a row, but cross moved last.
+        let game: Game = "OOO....../OOO....../OOO....../XX......./XX......./\
+                          XX......./XX......./XX......./......... O -"
+            .parse()
+            .unwrap();
+        assert_eq!(validate(&game), vec![Problem::PlayedAfterGameOver]);
+    }
+}
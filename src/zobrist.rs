@@ -0,0 +1,76 @@
+//! Zobrist keys, which hash a position as the XOR of one random key per feature.
+//! Placing or removing a token only has to XOR its key into the hash.
+//!
+//! The keys are derived from the feature with a fixed mixing function instead of being
+//! stored in tables, so hashes stay the same between runs.
+
+use board;
+use coord;
+
+/// The SplitMix64 finalizer, which turns consecutive numbers into unrelated ones.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// The key of a token at the given position. Clear cells don't change the hash.
+pub fn token(position: coord::Global, token: board::Token) -> u64 {
+    match token {
+        board::Token::Clear => 0,
+        board::Token::Cross => mix(position.index() as u64),
+        board::Token::Circle => mix(81 + position.index() as u64),
+    }
+}
+
+/// The key of the player to move. Cross moves first and doesn't change the hash.
+pub fn player(player: board::Player) -> u64 {
+    match player {
+        board::Player::Cross => 0,
+        board::Player::Circle => mix(162),
+    }
+}
+
+/// The key of the region the player to move is sent to. A free move doesn't change the hash.
+pub fn active_region(region: Option<coord::Local>) -> u64 {
+    match region {
+        None => 0,
+        Some(region) => mix(163 + region.index() as u64),
+    }
+}
+
+/// The key of the owner of a region. Undecided and drawn regions don't change the hash,
+/// their cells already tell them apart.
+pub fn owner(region: coord::Local, owner: board::Ownership) -> u64 {
+    match owner {
+        board::Ownership::Undecided | board::Ownership::Draw => 0,
+        board::Ownership::Cross => mix(172 + region.index() as u64),
+        board::Ownership::Circle => mix(181 + region.index() as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zobrist::*;
+
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys: Vec<u64> = vec![player(board::Player::Circle)];
+        for position in coord::Global::iter() {
+            keys.push(token(position, board::Token::Cross));
+            keys.push(token(position, board::Token::Circle));
+        }
+        for region in coord::Local::iter() {
+            keys.push(active_region(Some(region)));
+            keys.push(owner(region, board::Ownership::Cross));
+            keys.push(owner(region, board::Ownership::Circle));
+        }
+
+        let count = keys.len();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), count);
+        assert!(keys.iter().all(|&key| key != 0));
+    }
+}